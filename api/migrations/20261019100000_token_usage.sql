@@ -0,0 +1,3 @@
+ALTER TABLE "users"
+    ADD COLUMN prompt_tokens_spent INT NOT NULL DEFAULT 0,
+    ADD COLUMN completion_tokens_spent INT NOT NULL DEFAULT 0;
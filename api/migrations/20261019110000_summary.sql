@@ -0,0 +1,3 @@
+ALTER TABLE "users"
+    ADD COLUMN summary TEXT,
+    ADD COLUMN summarized_messages INT NOT NULL DEFAULT 0;
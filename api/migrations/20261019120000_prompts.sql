@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS "prompts" (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    version INT NOT NULL,
+    text TEXT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    UNIQUE (name, version)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS prompts_active_name ON "prompts" (name) WHERE active;
+
+ALTER TABLE "users"
+    ADD COLUMN resume_prompt_version TEXT;
@@ -0,0 +1,26 @@
+ALTER TABLE "users"
+    ADD COLUMN tenant TEXT NOT NULL DEFAULT 'default';
+
+ALTER TABLE "prompts"
+    ADD COLUMN tenant TEXT NOT NULL DEFAULT 'default',
+    ADD COLUMN created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now();
+
+ALTER TABLE "prompts" DROP CONSTRAINT IF EXISTS prompts_name_version_key;
+ALTER TABLE "prompts" ADD CONSTRAINT prompts_tenant_name_version_key UNIQUE (tenant, name, version);
+
+DROP INDEX IF EXISTS prompts_active_name;
+CREATE UNIQUE INDEX IF NOT EXISTS prompts_active_tenant_name ON "prompts" (tenant, name) WHERE active;
+
+CREATE TABLE IF NOT EXISTS "tool_schemas" (
+    id SERIAL PRIMARY KEY,
+    tenant TEXT NOT NULL DEFAULT 'default',
+    name TEXT NOT NULL,
+    version INT NOT NULL,
+    description TEXT NOT NULL,
+    parameters JSONB NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    UNIQUE (tenant, name, version)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS tool_schemas_active_tenant_name ON "tool_schemas" (tenant, name) WHERE active;
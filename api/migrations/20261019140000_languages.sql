@@ -0,0 +1,12 @@
+ALTER TABLE "users"
+    ADD COLUMN language TEXT,
+    ADD COLUMN cv_language TEXT;
+
+ALTER TABLE "prompts"
+    ADD COLUMN language TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE "prompts" DROP CONSTRAINT IF EXISTS prompts_tenant_name_version_key;
+ALTER TABLE "prompts" ADD CONSTRAINT prompts_tenant_name_language_version_key UNIQUE (tenant, name, language, version);
+
+DROP INDEX IF EXISTS prompts_active_tenant_name;
+CREATE UNIQUE INDEX IF NOT EXISTS prompts_active_tenant_name_language ON "prompts" (tenant, name, language) WHERE active;
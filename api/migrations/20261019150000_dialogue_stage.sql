@@ -0,0 +1,26 @@
+ALTER TABLE "users"
+    ADD COLUMN stage TEXT NOT NULL DEFAULT 'profession'
+        CHECK (stage IN ('profession', 'questions', 'answers', 'resume', 'done'));
+
+UPDATE "users" SET stage = CASE
+    WHEN resume IS NOT NULL THEN 'done'
+    WHEN questions IS NOT NULL AND NOT EXISTS (
+        SELECT 1 FROM jsonb_array_elements(questions) q
+        WHERE q->'answer' IS NULL OR q->'answer' = 'null'::JSONB
+    ) THEN 'resume'
+    WHEN questions IS NOT NULL THEN 'answers'
+    WHEN profession IS NOT NULL THEN 'questions'
+    ELSE 'profession'
+END;
+
+CREATE TABLE IF NOT EXISTS "dialogue_events" (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES "users" (id) ON DELETE CASCADE,
+    from_stage TEXT NOT NULL,
+    to_stage TEXT NOT NULL,
+    trigger TEXT NOT NULL,
+    detail TEXT,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS dialogue_events_user ON "dialogue_events" (user_id, id);
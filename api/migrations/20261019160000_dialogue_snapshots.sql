@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS "dialogue_snapshots" (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES "users" (id) ON DELETE CASCADE,
+    stage TEXT NOT NULL,
+    profession TEXT,
+    questions JSONB,
+    messages JSONB NOT NULL DEFAULT '[]'::JSONB,
+    summary TEXT,
+    summarized_messages INT NOT NULL DEFAULT 0,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS dialogue_snapshots_user ON "dialogue_snapshots" (user_id, id);
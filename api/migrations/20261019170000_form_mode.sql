@@ -0,0 +1,2 @@
+ALTER TABLE "users"
+    ADD COLUMN mode TEXT NOT NULL DEFAULT 'chat' CHECK (mode IN ('chat', 'form'));
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS "vacancies" (
+    id SERIAL PRIMARY KEY,
+    user_id INT NOT NULL REFERENCES "users" (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    resume TEXT,
+    resume_prompt_version TEXT,
+    created TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS vacancies_user ON "vacancies" (user_id, id);
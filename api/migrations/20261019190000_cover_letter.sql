@@ -0,0 +1,3 @@
+ALTER TABLE "users"
+    ADD COLUMN cover_letter TEXT,
+    ADD COLUMN cover_letter_prompt_version TEXT;
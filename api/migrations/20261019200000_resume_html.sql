@@ -0,0 +1,5 @@
+ALTER TABLE "users"
+    ADD COLUMN resume_html TEXT;
+
+ALTER TABLE "vacancies"
+    ADD COLUMN resume_html TEXT;
@@ -0,0 +1,4 @@
+ALTER TABLE "users"
+    DROP CONSTRAINT users_stage_check,
+    ADD CONSTRAINT users_stage_check
+        CHECK (stage IN ('profession', 'questions', 'answers', 'resume', 'done', 'review'));
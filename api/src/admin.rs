@@ -0,0 +1,362 @@
+use std::env;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{from_fn, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::error;
+use crate::AppState;
+use crate::ask::language_directive;
+use crate::{db, language};
+use crate::openai::resolve_model;
+use crate::prompts::{Prompt, PromptName};
+use crate::reply::validation_error;
+use crate::tokens;
+use crate::tools::{validate_parameters, Tool, ToolName};
+use crate::user::User;
+
+const MAX_PROMPT_LENGTH: usize = 50_000;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/tenants/:tenant/prompts", get(prompts_list))
+        .route("/tenants/:tenant/prompts/:name", get(prompt_get).put(prompt_put))
+        .route("/tenants/:tenant/prompts/:name/preview", post(prompt_preview))
+        .route("/tenants/:tenant/prompts/:name/rollback", post(prompt_rollback))
+        .route("/tenants/:tenant/tools", get(tools_list))
+        .route("/tenants/:tenant/tools/:name", get(tool_get).put(tool_put))
+        .route("/tenants/:tenant/tools/:name/preview", post(tool_preview))
+        .route("/tenants/:tenant/tools/:name/rollback", post(tool_rollback))
+        .route("/users/:id/timeline", get(user_timeline))
+        .route_layer(from_fn(authorize))
+}
+
+/// Admin routes require `Authorization: Bearer $ADMIN_TOKEN`; without the variable they are closed.
+async fn authorize(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let token = env::var("ADMIN_TOKEN").map_err(|_| StatusCode::FORBIDDEN)?;
+
+    let authorized = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .is_some_and(|t| !token.is_empty() && t == token);
+
+    match authorized {
+        true => Ok(next.run(request).await),
+        false => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+fn db_error(e: sqlx::Error) -> Response {
+    error!("admin db error: {e:?}");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+async fn reload(app_state: &AppState) {
+    if let Err(e) = app_state.prompts.reload(&app_state.pool).await {
+        error!("Failed to reload prompts: {e}");
+    }
+}
+
+fn validate_prompt(text: &str) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+
+    if text.trim().is_empty() {
+        errors.push("prompt text is empty".to_string());
+    }
+    if text.len() > MAX_PROMPT_LENGTH {
+        errors.push(format!("prompt text is longer than {MAX_PROMPT_LENGTH} bytes"));
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LanguageQuery {
+    /// Localised variant to work with; the language-neutral prompt when omitted.
+    language: Option<String>,
+}
+
+impl LanguageQuery {
+    fn code(&self) -> Result<&'static str, String> {
+        match &self.language {
+            None => Ok(""),
+            Some(code) => language::normalize(code).ok_or_else(|| format!("unsupported language \"{code}\"")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptBody {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToolBody {
+    description: String,
+    parameters: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Rollback {
+    /// Version to activate; the one before the active version when omitted.
+    version: Option<i32>,
+}
+
+/// Version to roll back to: the requested one, else the newest one older than the active version.
+/// `None` means no stored version remains and the built-in default applies again.
+fn rollback_target(requested: Option<i32>, versions: &[(i32, bool)]) -> Option<i32> {
+    if requested.is_some() {
+        return requested;
+    }
+
+    let active = versions.iter().find(|(_, active)| *active).map(|(v, _)| *v)?;
+    versions.iter().map(|(v, _)| *v).filter(|v| *v < active).max()
+}
+
+async fn prompts_list(
+    Path(tenant): Path<String>,
+    Query(query): Query<LanguageQuery>,
+    State(app_state): State<AppState>,
+) -> Response {
+    let language = match query.code() {
+        Ok(language) => Some(language).filter(|l| !l.is_empty()),
+        Err(e) => return validation_error(vec![e]),
+    };
+    let prompts: Vec<Prompt> = PromptName::ALL.into_iter()
+        .map(|name| app_state.prompts.get(&tenant, name, language))
+        .collect();
+
+    Json(prompts).into_response()
+}
+
+async fn prompt_get(
+    Path((tenant, name)): Path<(String, String)>,
+    Query(query): Query<LanguageQuery>,
+    State(app_state): State<AppState>,
+) -> Response {
+    let Some(prompt_name) = PromptName::from_name(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let language = match query.code() {
+        Ok(language) => language,
+        Err(e) => return validation_error(vec![e]),
+    };
+
+    match db::load_prompt_versions(&app_state.pool, &tenant, &name, language).await {
+        Ok(versions) => Json(json!({
+            "current": app_state.prompts.get(&tenant, prompt_name, Some(language).filter(|l| !l.is_empty())),
+            "versions": versions,
+        })).into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+async fn prompt_put(
+    Path((tenant, name)): Path<(String, String)>,
+    Query(query): Query<LanguageQuery>,
+    State(app_state): State<AppState>,
+    Json(body): Json<PromptBody>,
+) -> Response {
+    if PromptName::from_name(&name).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let language = match query.code() {
+        Ok(language) => language,
+        Err(e) => return validation_error(vec![e]),
+    };
+    if let Err(errors) = validate_prompt(&body.text) {
+        return validation_error(errors);
+    }
+
+    match db::add_prompt_version(&app_state.pool, &tenant, &name, language, &body.text).await {
+        Ok(version) => {
+            reload(&app_state).await;
+            (StatusCode::CREATED, Json(json!({ "version": version }))).into_response()
+        }
+        Err(e) => db_error(e),
+    }
+}
+
+/// Shows what would be sent to the model with the candidate prompt, without storing it;
+/// with a language, also the instruction to answer in it that users of that language get.
+async fn prompt_preview(
+    Path((tenant, name)): Path<(String, String)>,
+    Query(query): Query<LanguageQuery>,
+    State(app_state): State<AppState>,
+    Json(body): Json<PromptBody>,
+) -> Response {
+    let Some(prompt_name) = PromptName::from_name(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let language = match query.code() {
+        Ok(language) => Some(language).filter(|l| !l.is_empty()),
+        Err(e) => return validation_error(vec![e]),
+    };
+    if let Err(errors) = validate_prompt(&body.text) {
+        return validation_error(errors);
+    }
+
+    let model = resolve_model(None);
+    let tools = app_state.prompts.get_tools(&tenant, prompt_name);
+    let raw_functions: Vec<_> = tools.iter().map(Tool::as_raw).collect();
+    let language_directive = language.map(|language| language_directive(prompt_name, language));
+    let prompt_tokens = tokens::count_text(&model, &body.text)
+        + language_directive.as_deref().map_or(0, |d| tokens::count_text(&model, d))
+        + tokens::count_tools(&model, &raw_functions);
+
+    Json(json!({
+        "system_message": body.text,
+        "language_directive": language_directive,
+        "tools": tools,
+        "model": model,
+        "prompt_tokens": prompt_tokens,
+    })).into_response()
+}
+
+async fn prompt_rollback(
+    Path((tenant, name)): Path<(String, String)>,
+    Query(query): Query<LanguageQuery>,
+    State(app_state): State<AppState>,
+    Json(body): Json<Rollback>,
+) -> Response {
+    if PromptName::from_name(&name).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let language = match query.code() {
+        Ok(language) => language,
+        Err(e) => return validation_error(vec![e]),
+    };
+
+    let versions = match db::load_prompt_versions(&app_state.pool, &tenant, &name, language).await {
+        Ok(versions) => versions.into_iter().map(|v| (v.version, v.active)).collect::<Vec<_>>(),
+        Err(e) => return db_error(e),
+    };
+    let target = rollback_target(body.version, &versions);
+
+    match db::activate_prompt_version(&app_state.pool, &tenant, &name, language, target).await {
+        Ok(true) => {
+            reload(&app_state).await;
+            Json(json!({ "version": target })).into_response()
+        }
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+async fn tools_list(Path(tenant): Path<String>, State(app_state): State<AppState>) -> impl IntoResponse {
+    let tools: Vec<Tool> = ToolName::ALL.into_iter()
+        .map(|name| app_state.prompts.get_tool(&tenant, name))
+        .collect();
+
+    Json(tools)
+}
+
+async fn tool_get(Path((tenant, name)): Path<(String, String)>, State(app_state): State<AppState>) -> Response {
+    let Some(tool_name) = ToolName::from_name(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match db::load_tool_versions(&app_state.pool, &tenant, &name).await {
+        Ok(versions) => Json(json!({
+            "current": app_state.prompts.get_tool(&tenant, tool_name),
+            "versions": versions,
+        })).into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+async fn tool_put(
+    Path((tenant, name)): Path<(String, String)>,
+    State(app_state): State<AppState>,
+    Json(body): Json<ToolBody>,
+) -> Response {
+    let Some(tool_name) = ToolName::from_name(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(errors) = validate_parameters(tool_name, &body.parameters) {
+        return validation_error(errors);
+    }
+
+    match db::add_tool_version(&app_state.pool, &tenant, &name, &body.description, &body.parameters).await {
+        Ok(version) => {
+            reload(&app_state).await;
+            (StatusCode::CREATED, Json(json!({ "version": version }))).into_response()
+        }
+        Err(e) => db_error(e),
+    }
+}
+
+/// Validates the candidate schema and shows the tool definition as it would be sent, without storing it.
+async fn tool_preview(
+    Path((_tenant, name)): Path<(String, String)>,
+    Json(body): Json<ToolBody>,
+) -> Response {
+    let Some(tool_name) = ToolName::from_name(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if let Err(errors) = validate_parameters(tool_name, &body.parameters) {
+        return validation_error(errors);
+    }
+
+    let model = resolve_model(None);
+    let tool = Tool {
+        name: tool_name,
+        description: body.description,
+        parameters: body.parameters,
+        version: "preview".to_string(),
+    };
+
+    Json(json!({
+        "prompt_tokens": tokens::count_tools(&model, &[tool.as_raw()]),
+        "tool": tool,
+        "model": model,
+    })).into_response()
+}
+
+async fn tool_rollback(
+    Path((tenant, name)): Path<(String, String)>,
+    State(app_state): State<AppState>,
+    Json(body): Json<Rollback>,
+) -> Response {
+    if ToolName::from_name(&name).is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    let versions = match db::load_tool_versions(&app_state.pool, &tenant, &name).await {
+        Ok(versions) => versions.into_iter().map(|v| (v.version, v.active)).collect::<Vec<_>>(),
+        Err(e) => return db_error(e),
+    };
+    let target = rollback_target(body.version, &versions);
+
+    match db::activate_tool_version(&app_state.pool, &tenant, &name, target).await {
+        Ok(true) => {
+            reload(&app_state).await;
+            Json(json!({ "version": target })).into_response()
+        }
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => db_error(e),
+    }
+}
+
+/// Current stage and every stage transition of a user, oldest first.
+async fn user_timeline(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match db::load_dialogue_events(&app_state.pool, id).await {
+        Ok(events) => Json(json!({
+            "user_id": id,
+            "stage": user.get_stage(),
+            "events": events,
+        })).into_response(),
+        Err(e) => db_error(e),
+    }
+}
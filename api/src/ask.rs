@@ -1,7 +1,17 @@
-use std::fs::read_to_string;
-use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionResponseMessage};
-use serde_json::{json, Value};
-use crate::openai::{ChatResponse, get_response, Request};
+use std::env;
+use async_openai::types::{ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionResponseMessage};
+use serde_json::Value;
+use tracing::warn;
+use crate::openai::{ChatResponse, DEFAULT_MAX_TOKENS, get_response, Request, resolve_model, TokenUsage};
+use crate::language;
+use crate::prompts::{PromptName, PromptRegistry};
+use crate::tokens;
+use crate::entry::Entry;
+use crate::tools::{validate_arguments, Tool, ToolName};
+use crate::user::QuestionInput;
+
+/// How many times the model is asked to fix tool call arguments that don't match the schema.
+const TOOL_REPAIR_ATTEMPTS: u32 = 2;
 
 
 #[derive(Debug)]
@@ -9,20 +19,34 @@ pub enum Response {
     Text(String),
     Error(String),
     Profession(ToolCallRequest, String),
-    Questions(ToolCallRequest, Vec<String>),
-    Answers(ChatCompletionRequestMessage, Vec<(ToolCallRequest, (u8, String))>),
+    Questions(ToolCallRequest, Vec<QuestionInput>),
+    Answers(ChatCompletionRequestMessage, Vec<(ToolCallRequest, AnswerCall)>),
     Resume(ToolCallRequest, String),
+    CoverLetter(String),
+    /// Answers improved during the review and, once the user agrees, the call to make the CV again with what changed.
+    Review(ChatCompletionRequestMessage, Vec<(ToolCallRequest, AnswerCall)>, Option<(ToolCallRequest, String)>),
+    LimitExceeded,
+}
+
+/// What a call in the answers stage does to the question at its index.
+#[derive(Debug)]
+pub enum AnswerCall {
+    Answer(u8, String),
+    Entry(u8, Entry),
+    /// A call that can't be applied; it still gets an error reply, as the history must answer every call.
+    Invalid(&'static str),
 }
 
 #[derive(Debug)]
 pub struct PayableResponse {
     pub response: Response,
-    pub tokens_spent: u32,
+    pub usage: TokenUsage,
+    pub prompt_version: String,
 }
 
 impl PayableResponse {
-    fn new(response: Response, tokens_spent: u32) -> Self {
-        Self { response, tokens_spent }
+    fn new(response: Response, usage: TokenUsage, prompt_version: String) -> Self {
+        Self { response, usage, prompt_version }
     }
 }
 
@@ -45,29 +69,35 @@ pub struct Asker {
     max_tokens: Option<u16>,
     model: Option<String>,
     system_message: Option<String>,
+    token_budget: Option<u32>,
+    prompts: PromptRegistry,
+    tenant: String,
+    language: Option<String>,
 }
 
 impl Asker {
-    pub fn new(api_key: String, max_tokens: Option<u16>, model: Option<String>, system_message: Option<String>) -> Self {
-        Asker { api_key, max_tokens, model, system_message }
+    pub fn new(api_key: String, max_tokens: Option<u16>, model: Option<String>, system_message: Option<String>, prompts: PromptRegistry, tenant: String) -> Self {
+        Asker { api_key, max_tokens, model, system_message, token_budget: None, prompts, tenant, language: None }
+    }
+
+    /// Language the model has to answer in; picks localised prompt variants when there are any.
+    pub fn set_language(&mut self, language: Option<String>) {
+        self.language = language;
+    }
+
+    pub fn model(&self) -> String {
+        resolve_model(self.model.clone())
+    }
+
+    /// Tokens the user may still spend; requests that can't fit are refused before sending.
+    pub fn set_token_budget(&mut self, token_budget: u32) {
+        self.token_budget = Some(token_budget);
     }
 
     pub async fn get_profession(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
         self.get_string(
             messages,
-            vec![
-                ("save_profession", "Save the profession", json!({
-                "type": "object",
-                "properties": {
-                    "profession": {
-                        "type": "string",
-                        "description": "Name of profession, e.g. Software Developer",
-                    },
-                },
-                "required": ["profession"],
-            }))
-            ],
-            "./src/data/prompt_profession.txt",
+            PromptName::Profession,
             "profession",
             Response::Profession,
         ).await
@@ -76,8 +106,7 @@ impl Asker {
     async fn get_string<F>(
         &self,
         messages: Vec<ChatCompletionRequestMessage>,
-        raw_functions: Vec<(&str, &str, Value)>,
-        file_path: &str,
+        prompt_name: PromptName,
         result_field_name: &str,
         response_type: F,
     ) -> PayableResponse
@@ -86,21 +115,23 @@ impl Asker {
     {
         return self.abstract_get(
             messages,
-            raw_functions,
-            file_path,
+            prompt_name,
             |tool_calls, response_message| {
-                if let Some(tool_call) = tool_calls.first() {
-                    let arguments: Value = tool_call.function.arguments.parse().unwrap();
-                    return response_type(
+                let result = tool_calls.first()
+                    .and_then(|tool_call| parse_json(&tool_call.function.arguments).ok().map(|args| (tool_call, args)))
+                    .and_then(|(tool_call, args)| args[result_field_name].as_str().map(|r| (tool_call, r.to_string())));
+
+                match result {
+                    Some((tool_call, result)) => response_type(
                         ToolCallRequest::new(
                             tool_call.id.clone(),
                             tool_call.function.name.clone(),
                             Some(to_request(response_message)),
                         ),
-                        serde_json::from_value(arguments[result_field_name].clone()).unwrap(),
-                    );
-                };
-                return Response::Error("Exception #4699740191".to_string());
+                        result,
+                    ),
+                    None => Response::Error("Exception #4699740191".to_string()),
+                }
             },
         ).await;
     }
@@ -108,45 +139,143 @@ impl Asker {
     async fn abstract_get<F>(
         &self,
         messages: Vec<ChatCompletionRequestMessage>,
-        raw_functions: Vec<(&str, &str, Value)>,
-        default_prompt_filepath: &str,
+        prompt_name: PromptName,
         custom_behavior: F,
     ) -> PayableResponse
         where
             F: Fn(&Vec<ChatCompletionMessageToolCall>, ChatCompletionResponseMessage) -> Response,
     {
-        let default_message = read_to_string(default_prompt_filepath)
-            .expect(&format!("Failed to get default message from \"{default_prompt_filepath}\""));
+        let (system_message, prompt_version) = match &self.system_message {
+            Some(message) => (message.clone(), "custom".to_string()),
+            None => {
+                let prompt = self.prompts.get(&self.tenant, prompt_name, self.language.as_deref());
+                (prompt.text, prompt.version)
+            }
+        };
+        let tools = self.prompts.get_tools(&self.tenant, prompt_name);
+        let raw_functions: Vec<(&str, &str, Value)> = tools.iter().map(Tool::as_raw).collect();
 
         let mut all_messages: Vec<ChatCompletionRequestMessage> = vec![
             ChatCompletionRequestMessage::System(
                 ChatCompletionRequestSystemMessageArgs::default()
-                    .content(
-                        match &self.system_message {
-                            Some(message) => message,
-                            None => &default_message
-                        }
-                    )
+                    .content(system_message)
                     .build()
                     .unwrap()
             )
         ];
+        if let Some(language) = &self.language {
+            all_messages.push(
+                ChatCompletionRequestMessage::System(
+                    ChatCompletionRequestSystemMessageArgs::default()
+                        .content(language_directive(prompt_name, language))
+                        .build()
+                        .unwrap()
+                )
+            );
+        }
         all_messages.extend(messages);
 
-        let get_result = self.get(
-            all_messages, raw_functions,
-        ).await;
+        let max_repairs = env::var("TOOL_REPAIR_ATTEMPTS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(TOOL_REPAIR_ATTEMPTS);
+        let mut usage = TokenUsage::default();
+        let mut repairs = 0;
 
-        let (tokens_spent, response) = match get_result {
-            Ok(chat_response) => {
-                (chat_response.tokens_spent, match chat_response.message.tool_calls {
-                    Some(ref tool_calls) => custom_behavior(tool_calls, chat_response.message.clone()),
-                    _ => Response::Text(chat_response.message.content.unwrap())
-                })
+        loop {
+            let prompt_tokens = match self.fit_prompt(&mut all_messages, &raw_functions, usage.total()) {
+                Some(prompt_tokens) => prompt_tokens,
+                None => return PayableResponse::new(Response::LimitExceeded, usage, prompt_version),
+            };
+
+            let chat_response = match self.get(all_messages.clone(), raw_functions.clone()).await {
+                Ok(chat_response) => chat_response,
+                Err(e) => return PayableResponse::new(Response::Error(e), usage, prompt_version),
+            };
+            usage += chat_response.usage.unwrap_or(TokenUsage::new(prompt_tokens, 0));
+
+            let Some(tool_calls) = chat_response.message.tool_calls.clone() else {
+                let text = chat_response.message.content.unwrap_or_default();
+                return PayableResponse::new(Response::Text(text), usage, prompt_version);
+            };
+
+            let errors: Vec<Option<Vec<String>>> = tool_calls.iter()
+                .map(|tool_call| check_tool_call(&tools, tool_call).err())
+                .collect();
+            if errors.iter().all(Option::is_none) {
+                let response = custom_behavior(&tool_calls, chat_response.message);
+                return PayableResponse::new(response, usage, prompt_version);
             }
-            Err(e) => (0, Response::Error(e.to_string())),
-        };
-        return PayableResponse::new(response, tokens_spent);
+
+            let report = describe_errors(&tool_calls, &errors);
+            if repairs >= max_repairs {
+                warn!("giving up on invalid tool calls after {repairs} repairs: {report}");
+                return PayableResponse::new(
+                    Response::Error(format!("invalid tool call arguments: {report}")),
+                    usage,
+                    prompt_version,
+                );
+            }
+            repairs += 1;
+            warn!("invalid tool calls, asking the model to repair them ({repairs}/{max_repairs}): {report}");
+
+            // the rejected calls and the reasons go back to the model; only the repaired answer is kept
+            all_messages.push(to_request(chat_response.message));
+            for (tool_call, errors) in tool_calls.iter().zip(errors) {
+                let content = match errors {
+                    Some(errors) => format!(
+                        "error: the arguments don't match the schema of {}, nothing was saved:\n{}\nCall it again with corrected arguments.",
+                        tool_call.function.name,
+                        errors.join("\n"),
+                    ),
+                    None => "not saved because other calls in this reply were invalid, call it again".to_string(),
+                };
+                all_messages.push(
+                    ChatCompletionRequestMessage::Tool(
+                        ChatCompletionRequestToolMessageArgs::default()
+                            .tool_call_id(&tool_call.id)
+                            .content(content)
+                            .build()
+                            .unwrap()
+                    )
+                );
+            }
+        }
+    }
+
+    /// Estimates the prompt size and drops the oldest history until it fits the model context.
+    /// Returns `None` when the request can't fit the context or the remaining token budget,
+    /// less the `spent` tokens of earlier attempts.
+    fn fit_prompt(&self, messages: &mut Vec<ChatCompletionRequestMessage>, raw_functions: &[(&str, &str, Value)], spent: u32) -> Option<u32> {
+        let model = self.model();
+        let completion_tokens = self.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize;
+        let context_window = tokens::context_window(&model);
+        let tools_tokens = tokens::count_tools(&model, raw_functions);
+
+        let mut prompt_tokens = tokens::count_messages(&model, messages) + tools_tokens;
+        while prompt_tokens + completion_tokens > context_window {
+            let first = messages.iter()
+                .position(|m| !matches!(m, ChatCompletionRequestMessage::System(_)))?;
+            // the last message is the one being answered, it can't be trimmed
+            if first + 1 >= messages.len() {
+                warn!("prompt of {prompt_tokens} tokens doesn't fit context of {context_window} ({model})");
+                return None;
+            }
+            messages.remove(first);
+            // tool results can't outlive the assistant message that requested them
+            while matches!(messages.get(first), Some(ChatCompletionRequestMessage::Tool(_))) {
+                messages.remove(first);
+            }
+            prompt_tokens = tokens::count_messages(&model, messages) + tools_tokens;
+        }
+
+        if let Some(budget) = self.token_budget {
+            if prompt_tokens + completion_tokens + spent as usize > budget as usize {
+                warn!("prompt of {prompt_tokens} tokens exceeds remaining budget of {budget}");
+                return None;
+            }
+        }
+
+        Some(prompt_tokens as u32)
     }
 
     async fn get(&self, messages: Vec<ChatCompletionRequestMessage>, raw_functions: Vec<(&str, &str, Value)>) -> Result<ChatResponse, String> {
@@ -158,31 +287,13 @@ impl Asker {
             raw_functions,
         );
 
-        return get_response(request).await.map_err(|err| format!("openai_error: {:?}", err));
+        get_response(request).await.map_err(|err| format!("openai_error: {err}"))
     }
 
     pub async fn get_questions(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
         return self.abstract_get(
             messages,
-            vec![
-                ("add_questions", "Set a list of questions for creating a resume for a profession", json!({
-                "type": "object",
-                "properties": {
-                    "questions": {
-                        "type": "array",
-                        "description": "A list of questions",
-                        "items": {
-                            "type": "string",
-                            "description": "Question, e.g. “full name”, “gender”, “name”, “work experience”, “list of programming languages studied”, “knowledge of the Django framework and at what level”",
-                        },
-                        "minItems": 5,
-                        "maxItems": 20,
-                    },
-                },
-                "required": ["questions"],
-            }))
-            ],
-            "./src/data/prompt_questions.txt",
+            PromptName::Questions,
             |tool_calls, response_message| {
                 for tool_call in tool_calls {
                     if let Ok(args) = parse_json(&tool_call.function.arguments) {
@@ -194,7 +305,7 @@ impl Asker {
                                     Some(to_request(response_message)),
                                 ),
                                 qs.iter()
-                                    .map(move |x| x.to_string())
+                                    .filter_map(parse_question)
                                     .collect(),
                             );
                         }
@@ -206,74 +317,68 @@ impl Asker {
     }
 
     pub async fn get_answers(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
-        return self.abstract_get(
-            messages,
-            vec![
-                ("set_answer", "Set answer to the survey question by index", json!({
-                "type": "object",
-                "properties": {
-                    "index": {
-                        "type": "integer",
-                        "description": "index question from the survey",
-                    },
-                    "answer": {
-                        "type": "string",
-                        "description": "answer to the survey question",
-                    },
-                },
-                "required": ["index", "answer"],
-            }))
-            ],
-            "./src/data/prompt_answers.txt",
-            |tool_calls, response_message| {
-                let mut answers: Vec<(ToolCallRequest, (u8, String))> = vec![];
-
-                for tool_call in tool_calls {
-                    if let Ok(args) = parse_json(&tool_call.function.arguments) {
-                        let index = args["index"].as_u64().unwrap() as u8;
-                        let answer = args["answer"].as_str().unwrap();
-                        answers.push(
-                            (
-                                ToolCallRequest::new(
-                                    tool_call.id.clone(),
-                                    tool_call.function.name.clone(),
-                                    None,
-                                ),
-                                (index, answer.to_string())
-                            )
-                        );
-                    }
-                }
+        self.abstract_get(messages, PromptName::Answers, |tool_calls, response_message| answer_calls(tool_calls, response_message)).await
+    }
 
-                return match answers.is_empty() {
-                    true => Response::Error("Exception #6407321013".to_string()),
-                    false => Response::Answers(to_request(response_message), answers)
-                };
-            },
-        ).await;
+    /// Answers as many questions as possible from the text of the user's existing CV.
+    pub async fn get_imported_answers(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
+        self.abstract_get(messages, PromptName::Import, |tool_calls, response_message| answer_calls(tool_calls, response_message)).await
     }
 
     pub async fn get_resume(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
         return self.get_string(
             messages,
-            vec![
-                ("save_resume", "Save the CV HTML summary", json!({
-                "type": "object",
-                "properties": {
-                    "cv_html": {
-                        "type": "string",
-                        "description": "the HTML of CV"
-                    },
-                },
-                "required": ["cv_html"],
-            }))
-            ],
-            "./src/data/prompt_resume.txt",
+            PromptName::Resume,
             "cv_html",
             Response::Resume,
         ).await;
     }
 
+    /// A resume rewritten for the vacancy in the last message.
+    pub async fn get_tailored_resume(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
+        self.get_string(
+            messages,
+            PromptName::Tailor,
+            "cv_html",
+            Response::Resume,
+        ).await
+    }
+
+    /// Critiques the CV in the messages and improves the answers behind it.
+    pub async fn get_review(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
+        self.abstract_get(messages, PromptName::Review, |tool_calls, response_message| review_calls(tool_calls, response_message)).await
+    }
+
+    /// A cover letter from the answers, for the vacancy in the last message if there is one.
+    pub async fn get_cover_letter(&self, messages: Vec<ChatCompletionRequestMessage>) -> PayableResponse {
+        self.get_string(
+            messages,
+            PromptName::CoverLetter,
+            "letter_html",
+            |_, letter| Response::CoverLetter(letter),
+        ).await
+    }
+
+    pub async fn get_summary(&self, previous_summary: Option<String>, transcript: String) -> PayableResponse {
+        let content = match previous_summary {
+            Some(summary) => format!("Summary so far:\n{summary}\n\nConversation:\n{transcript}"),
+            None => format!("Conversation:\n{transcript}"),
+        };
+
+        self.abstract_get(
+            vec![
+                ChatCompletionRequestMessage::User(
+                    ChatCompletionRequestUserMessageArgs::default()
+                        .content(content)
+                        .build()
+                        .unwrap()
+                )
+            ],
+            PromptName::Summary,
+            |_, _| Response::Error("Exception #8812093714".to_string()),
+        ).await
+    }
+
     pub fn clone_with_max_tokens(&self, max_tokens: u16) -> Self {
         let mut clone = self.clone();
         clone.max_tokens = Some(max_tokens);
@@ -282,6 +387,118 @@ impl Asker {
     }
 }
 
+/// Collects `set_answer`, `add_job` and `add_education` calls by question index.
+fn answer_calls(tool_calls: &[ChatCompletionMessageToolCall], response_message: ChatCompletionResponseMessage) -> Response {
+    let answers = parse_answer_calls(tool_calls);
+    match answers.is_empty() {
+        true => Response::Error("Exception #6407321013".to_string()),
+        false => Response::Answers(to_request(response_message), answers)
+    }
+}
+
+/// Like [`answer_calls`], also taking the `regenerate_resume` call that ends the review.
+fn review_calls(tool_calls: &[ChatCompletionMessageToolCall], response_message: ChatCompletionResponseMessage) -> Response {
+    let mut answers = parse_answer_calls(tool_calls);
+    let mut regenerate_calls = tool_calls.iter()
+        .filter(|tool_call| ToolName::from_name(&tool_call.function.name) == Some(ToolName::RegenerateResume));
+    let regenerate = regenerate_calls.next()
+        .map(|tool_call| {
+            let changes = parse_json(&tool_call.function.arguments).ok()
+                .and_then(|args| args["changes"].as_str().map(str::to_string))
+                .unwrap_or_default();
+            (ToolCallRequest::new(tool_call.id.clone(), tool_call.function.name.clone(), None), changes)
+        });
+    answers.extend(regenerate_calls.map(|tool_call| (
+        ToolCallRequest::new(tool_call.id.clone(), tool_call.function.name.clone(), None),
+        AnswerCall::Invalid("the CV is regenerated once, by the first regenerate_resume call"),
+    )));
+
+    match answers.is_empty() && regenerate.is_none() {
+        true => Response::Error("Exception #3170945528".to_string()),
+        false => Response::Review(to_request(response_message), answers, regenerate)
+    }
+}
+
+/// Every call except `regenerate_resume`, which the review handles itself; the ones that can't be applied come back
+/// as [`AnswerCall::Invalid`] rather than being dropped.
+fn parse_answer_calls(tool_calls: &[ChatCompletionMessageToolCall]) -> Vec<(ToolCallRequest, AnswerCall)> {
+    tool_calls.iter()
+        .filter(|tool_call| ToolName::from_name(&tool_call.function.name) != Some(ToolName::RegenerateResume))
+        .map(|tool_call| (
+            ToolCallRequest::new(tool_call.id.clone(), tool_call.function.name.clone(), None),
+            parse_answer_call(tool_call),
+        ))
+        .collect()
+}
+
+fn parse_answer_call(tool_call: &ChatCompletionMessageToolCall) -> AnswerCall {
+    let Ok(args) = parse_json(&tool_call.function.arguments) else {
+        return AnswerCall::Invalid("the arguments are not valid JSON");
+    };
+    let Some(Ok(index)) = args["index"].as_u64().map(u8::try_from) else {
+        return AnswerCall::Invalid("index must be a question number");
+    };
+    match ToolName::from_name(&tool_call.function.name) {
+        Some(ToolName::AddJob) => match serde_json::from_value(args) {
+            Ok(job) => AnswerCall::Entry(index, Entry::Job(job)),
+            Err(_) => AnswerCall::Invalid("the job doesn't match the add_job schema"),
+        },
+        Some(ToolName::AddEducation) => match serde_json::from_value(args) {
+            Ok(study) => AnswerCall::Entry(index, Entry::Study(study)),
+            Err(_) => AnswerCall::Invalid("the education doesn't match the add_education schema"),
+        },
+        Some(ToolName::SetAnswer) => match args["answer"].as_str() {
+            Some(answer) => AnswerCall::Answer(index, answer.to_string()),
+            None => AnswerCall::Invalid("answer must be a string"),
+        },
+        _ => AnswerCall::Invalid("unknown tool"),
+    }
+}
+
+/// Tells the model which language to write in, after the prompt itself.
+pub fn language_directive(prompt_name: PromptName, language: &str) -> String {
+    let name = language::name(language);
+    match prompt_name {
+        PromptName::Resume | PromptName::Tailor => format!(
+            "Write the whole CV in {name}, including section headings. Translate the answers where needed, \
+            but keep names, e-mails, links and company names as they are."
+        ),
+        PromptName::CoverLetter => format!(
+            "Write the whole letter in {name}, but keep names, e-mails, links and company names as they are."
+        ),
+        PromptName::Questions => format!("Write the questions in {name}."),
+        PromptName::Summary => format!("Write the summary in {name}."),
+        PromptName::Import => format!("Write the answers in {name}, translating them from the CV where needed."),
+        PromptName::Profession | PromptName::Answers | PromptName::Review => format!(
+            "Talk to the user in {name}: write every reply and question in {name}, \
+            even though these instructions are in English."
+        ),
+    }
+}
+
+/// Validates a tool call against the schemas offered in the request.
+fn check_tool_call(tools: &[Tool], tool_call: &ChatCompletionMessageToolCall) -> Result<Value, Vec<String>> {
+    match tools.iter().find(|t| t.name.as_str() == tool_call.function.name) {
+        Some(tool) => validate_arguments(tool, &tool_call.function.arguments),
+        None => Err(vec![format!("unknown tool \"{}\"", tool_call.function.name)]),
+    }
+}
+
+fn describe_errors(tool_calls: &[ChatCompletionMessageToolCall], errors: &[Option<Vec<String>>]) -> String {
+    tool_calls.iter().zip(errors)
+        .filter_map(|(tool_call, errors)| errors.as_ref().map(|e| format!("{}: {}", tool_call.function.name, e.join("; "))))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Questions are objects with an answer type; tenants may still have a schema with plain strings.
+fn parse_question(item: &Value) -> Option<QuestionInput> {
+    match item.as_str() {
+        Some(question) => Some(QuestionInput::new(question)),
+        None => serde_json::from_value(item.clone()).ok(),
+    }
+}
+
 fn parse_json(json_str: &str) -> Result<Value, serde_json::Error> {
     serde_json::from_str(json_str)
 }
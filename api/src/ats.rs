@@ -0,0 +1,466 @@
+use std::collections::{HashMap, HashSet};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use crate::{db, language};
+use crate::i18n::{t, t_with};
+use crate::reply::conflict;
+use crate::user::User;
+use crate::AppState;
+
+/// How many of the most frequent job description words are looked for in the CV.
+const MAX_KEYWORDS: usize = 25;
+/// How many missing keywords a suggestion names.
+const MAX_SUGGESTED_KEYWORDS: usize = 10;
+/// With a job description, the score is this share structure and the rest keyword coverage.
+const STRUCTURE_SHARE: f32 = 0.6;
+const MIN_WORDS: usize = 150;
+const MAX_WORDS: usize = 1_200;
+
+/// Headings ATS parsers look for, in the languages of the interview.
+const EXPERIENCE_HEADINGS: &[&str] = &[
+    "experience", "employment", "work history", "career",
+    "опыт", "досвід", "berufserfahrung", "erfahrung", "experiencia", "expérience",
+];
+const EDUCATION_HEADINGS: &[&str] = &[
+    "education", "qualifications", "academic",
+    "образование", "освіта", "ausbildung", "bildung", "studium", "educación", "formación", "formation",
+];
+const SKILLS_HEADINGS: &[&str] = &[
+    "skills", "competencies", "expertise", "technologies",
+    "навыки", "навички", "умения", "kenntnisse", "fähigkeiten", "kompetenzen", "habilidades", "competencias", "compétences",
+];
+const SUMMARY_HEADINGS: &[&str] = &[
+    "summary", "profile", "about", "objective",
+    "о себе", "резюме", "профиль", "про себе", "профіль", "profil", "über mich", "zusammenfassung", "perfil", "resumen", "sobre mí",
+];
+
+/// Words of a job description that say nothing about the job itself, by language.
+/// English is always left out too, as job ads in any language mix in English.
+const STOP_WORDS: &[(&str, &[&str])] = &[
+    ("en", &[
+        "a", "about", "above", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been", "being",
+        "both", "but", "by", "can", "could", "do", "does", "each", "etc", "for", "from", "get", "had", "has", "have",
+        "how", "if", "in", "into", "is", "it", "its", "just", "may", "more", "most", "must", "new", "not", "of", "on",
+        "or", "other", "our", "out", "over", "own", "per", "same", "should", "so", "some", "such", "than", "that",
+        "the", "their", "them", "then", "there", "these", "they", "this", "those", "through", "to", "under", "up",
+        "us", "very", "was", "we", "well", "were", "what", "when", "where", "which", "while", "who", "whom", "why",
+        "will", "with", "within", "would", "you", "your", "yours",
+        "ability", "able", "apply", "benefits", "candidate", "candidates", "company", "experience", "good", "great",
+        "help", "hiring", "ideal", "including", "job", "join", "looking", "need", "nice", "offer", "opportunity", "plus", "position",
+        "preferred", "required", "requirements", "responsibilities", "role", "skills", "strong", "team", "work",
+        "want", "welcome", "working", "year", "years",
+    ]),
+    ("ru", &[
+        "и", "в", "во", "на", "с", "со", "по", "для", "от", "до", "из", "за", "к", "о", "об", "не", "или", "а", "но",
+        "что", "как", "мы", "вы", "наш", "наша", "наши", "ваш", "будет", "быть", "это", "также", "опыт", "работы",
+        "работа", "лет", "год", "года", "знание", "умение", "требования", "обязанности", "условия", "команда",
+    ]),
+    ("uk", &[
+        "і", "й", "та", "в", "у", "на", "з", "із", "зі", "по", "для", "від", "до", "за", "про", "не", "або", "а", "але",
+        "що", "як", "ми", "ви", "наш", "наша", "наші", "ваш", "буде", "бути", "це", "також", "досвід", "роботи",
+        "робота", "років", "рік", "року", "знання", "вміння", "вимоги", "умови", "команда", "команді",
+    ]),
+    ("de", &[
+        "der", "die", "das", "den", "dem", "des", "ein", "eine", "einen", "einem", "einer", "und", "oder", "aber",
+        "mit", "für", "von", "zu", "zum", "zur", "im", "in", "an", "am", "auf", "aus", "bei", "nach", "über", "unter",
+        "als", "auch", "wie", "wir", "du", "sie", "ihr", "ihre", "dein", "deine", "dich", "dir", "unser", "unsere",
+        "uns", "ist", "sind", "bist", "wird", "werden", "hast", "haben", "hat", "sein", "nicht", "sowie", "sehr",
+        "gute", "guter", "gutes", "erfahrung", "kenntnisse", "aufgaben", "anforderungen", "profil", "jahre", "jahren",
+        "bieten", "suchen", "stelle",
+    ]),
+    ("es", &[
+        "el", "la", "los", "las", "un", "una", "unos", "unas", "y", "e", "o", "u", "de", "del", "al", "en", "con",
+        "por", "para", "sin", "sobre", "entre", "que", "como", "es", "son", "ser", "será", "se", "su", "sus", "tu",
+        "tus", "nuestro", "nuestra", "nuestros", "nuestras", "nos", "te", "lo", "le", "les", "muy", "más", "también",
+        "no", "experiencia", "equipo", "años", "año", "requisitos", "ofrecemos", "buscamos", "puesto",
+        "conocimientos", "empresa",
+    ]),
+    ("fr", &[
+        "le", "la", "les", "un", "une", "des", "du", "de", "et", "ou", "en", "au", "aux", "dans", "sur", "pour",
+        "par", "avec", "sans", "que", "qui", "est", "sont", "être", "sera", "ce", "cette", "ces", "se", "sa", "son",
+        "ses", "nous", "vous", "notre", "nos", "votre", "vos", "ne", "pas", "plus", "très", "aussi", "expérience",
+        "équipe", "ans", "année", "poste", "profil", "missions", "entreprise", "compétences",
+    ]),
+];
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    name: &'static str,
+    passed: bool,
+    /// Points of the structure score the check is worth.
+    weight: u8,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeywordReport {
+    /// Share of the keywords found in the CV, in percent.
+    coverage: u8,
+    matched: Vec<String>,
+    missing: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// 0 to 100.
+    score: u8,
+    checks: Vec<Check>,
+    keywords: Option<KeywordReport>,
+    suggestions: Vec<String>,
+}
+
+/// Text, headings and markup of a CV, read without a full HTML parser.
+struct Document {
+    /// Lowercase names of every tag opened.
+    tags: HashSet<String>,
+    /// Contents of `<style>` elements and `style` attributes, lowercase and without whitespace.
+    css: String,
+    headings: Vec<String>,
+    text: String,
+}
+
+impl Document {
+    fn parse(html: &str) -> Self {
+        let mut tags = HashSet::new();
+        let mut css = String::new();
+        let mut headings = vec![];
+        let mut text = String::new();
+        let mut heading: Option<String> = None;
+        let mut skipped: Option<String> = None;
+
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let content = decode(&rest[..start]);
+            match skipped.as_deref() {
+                Some("style") => css.push_str(&content),
+                Some(_) => {}
+                None => {
+                    text.push_str(&content);
+                    if let Some(heading) = heading.as_mut() {
+                        heading.push_str(&content);
+                    }
+                }
+            }
+
+            let Some(end) = rest[start..].find('>') else {
+                rest = "";
+                break;
+            };
+            let tag = &rest[start + 1..start + end];
+            rest = &rest[start + end + 1..];
+
+            let closing = tag.starts_with('/');
+            let name: String = tag.trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_lowercase();
+            if name.is_empty() {
+                continue;
+            }
+            if let Some(style) = attribute(tag, "style") {
+                css.push_str(style);
+            }
+
+            match (closing, name.as_str()) {
+                (false, "style" | "script") => skipped = Some(name.clone()),
+                (true, "style" | "script") => skipped = None,
+                (false, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") => heading = Some(String::new()),
+                (true, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") => headings.extend(heading.take().map(|h| h.trim().to_lowercase())),
+                _ => {}
+            }
+            if !closing {
+                tags.insert(name);
+            }
+            // Tags separate words, so `<li>a</li><li>b</li>` doesn't read as "ab".
+            text.push(' ');
+        }
+        text.push_str(&decode(rest));
+
+        Document {
+            tags,
+            css: css.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase(),
+            headings,
+            text,
+        }
+    }
+
+    fn has_heading(&self, names: &[&str]) -> bool {
+        self.headings.iter().any(|h| names.iter().any(|n| h.contains(n)))
+    }
+
+    fn has_email(&self) -> bool {
+        self.text.split_whitespace().any(|w| {
+            let w = w.trim_matches(|c: char| !c.is_alphanumeric());
+            w.split_once('@').is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'))
+        })
+    }
+
+    /// A run of at least nine digits with the usual separators; date ranges are split first,
+    /// so "2019-06 - 2023-01" doesn't pass for a number.
+    fn has_phone(&self) -> bool {
+        self.text.split(['–', '—'])
+            .flat_map(|part| part.split(" - "))
+            .flat_map(|part| part.split(|c: char| !(c.is_ascii_digit() || matches!(c, ' ' | '-' | '(' | ')' | '.' | '+'))))
+            .any(|run| run.chars().filter(char::is_ascii_digit).count() >= 9)
+    }
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lower = tag.to_ascii_lowercase();
+    let start = lower.find(&format!("{name}="))? + name.len() + 1;
+    let value = &tag[start..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    value[1..].split(quote).next()
+}
+
+fn decode(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Lowercase words, keeping the characters of names such as "C++", "C#" and "Node.js".
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !(c.is_alphanumeric() || matches!(c, '+' | '#' | '.')))
+        .map(|w| w.trim_matches('.').to_lowercase())
+        .filter(|w| !w.is_empty())
+}
+
+/// Stop words for the language of the job description, all of them when it can't be told;
+/// `None` for a language without a list, whose function words would be taken for keywords.
+fn stop_words(description: &str) -> Option<HashSet<&'static str>> {
+    let language = language::detect(description);
+    if language.is_some_and(|language| !STOP_WORDS.iter().any(|(code, _)| *code == language)) {
+        return None;
+    }
+    Some(STOP_WORDS.iter()
+        .filter(|(code, _)| language.is_none_or(|language| *code == "en" || *code == language))
+        .flat_map(|(_, words)| words.iter().copied())
+        .collect())
+}
+
+/// The most frequent meaningful words of the job description, in the order they first appear.
+fn keywords(description: &str, stop_words: &HashSet<&str>) -> Vec<String> {
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+    for (position, word) in words(description).enumerate() {
+        if word.chars().count() < 2 || word.chars().all(|c| c.is_ascii_digit()) || stop_words.contains(word.as_str()) {
+            continue;
+        }
+        counts.entry(word).or_insert((0, position)).0 += 1;
+    }
+
+    let mut keywords: Vec<(String, (usize, usize))> = counts.into_iter().collect();
+    keywords.sort_by(|(_, (count0, first0)), (_, (count1, first1))| count1.cmp(count0).then(first0.cmp(first1)));
+    keywords.truncate(MAX_KEYWORDS);
+    keywords.sort_by_key(|(_, (_, first))| *first);
+    keywords.into_iter().map(|(word, _)| word).collect()
+}
+
+/// A keyword counts as found in its singular or plural form.
+fn keyword_report(document: &Document, description: &str) -> Option<KeywordReport> {
+    let stop_words = stop_words(description)?;
+    let cv_words: HashSet<String> = words(&document.text).collect();
+    let found = |keyword: &str| {
+        cv_words.contains(keyword)
+            || cv_words.contains(&format!("{keyword}s"))
+            || keyword.strip_suffix('s').is_some_and(|k| cv_words.contains(k))
+    };
+
+    let (matched, missing): (Vec<String>, Vec<String>) = keywords(description, &stop_words).into_iter().partition(|k| found(k));
+    let total = matched.len() + missing.len();
+    let coverage = match total {
+        0 => 100,
+        total => (matched.len() * 100 / total) as u8,
+    };
+    Some(KeywordReport { coverage, matched, missing })
+}
+
+/// Checks a CV for what commonly trips up applicant tracking systems and, given a job description,
+/// how many of its keywords the CV mentions. The same CV always gets the same report.
+pub fn analyse(html: &str, description: Option<&str>, language: Option<&str>) -> Report {
+    let document = Document::parse(html);
+    let word_count = document.text.split_whitespace().count();
+    let css = &document.css;
+
+    let checks = [
+        ("no_tables", 15, !document.tags.contains("table"),
+            "ats_no_tables"),
+        ("no_images", 10, !["img", "svg", "canvas", "picture", "object"].iter().any(|t| document.tags.contains(*t)) && !css.contains("background-image"),
+            "ats_no_images"),
+        ("single_column", 10, !["display:grid", "column-count", "columns:", "float:left", "float:right"].iter().any(|p| css.contains(p)),
+            "ats_single_column"),
+        ("no_header_footer", 5, !document.tags.contains("header") && !document.tags.contains("footer"),
+            "ats_no_header_footer"),
+        ("experience_heading", 10, document.has_heading(EXPERIENCE_HEADINGS),
+            "ats_experience_heading"),
+        ("education_heading", 10, document.has_heading(EDUCATION_HEADINGS),
+            "ats_education_heading"),
+        ("skills_heading", 10, document.has_heading(SKILLS_HEADINGS),
+            "ats_skills_heading"),
+        ("summary_heading", 5, document.has_heading(SUMMARY_HEADINGS),
+            "ats_summary_heading"),
+        ("email", 10, document.has_email(),
+            "ats_email"),
+        ("phone", 5, document.has_phone(),
+            "ats_phone"),
+        ("length", 10, (MIN_WORDS..=MAX_WORDS).contains(&word_count),
+            match word_count < MIN_WORDS {
+                true => "ats_too_short",
+                false => "ats_too_long",
+            }),
+    ];
+
+    let mut suggestions: Vec<String> = checks.iter()
+        .filter(|(_, _, passed, _)| !passed)
+        .map(|(_, _, _, suggestion)| t(language, suggestion))
+        .collect();
+    let structure: u32 = checks.iter().filter(|(_, _, passed, _)| *passed).map(|(_, weight, _, _)| *weight as u32).sum();
+    let checks: Vec<Check> = checks.into_iter()
+        .map(|(name, weight, passed, _)| Check { name, passed, weight })
+        .collect();
+
+    let keywords = description.and_then(|d| keyword_report(&document, d));
+    let score = match &keywords {
+        Some(keywords) => (structure as f32 * STRUCTURE_SHARE + keywords.coverage as f32 * (1.0 - STRUCTURE_SHARE)).round() as u8,
+        None => structure as u8,
+    };
+    if let Some(keywords) = keywords.as_ref().filter(|k| !k.missing.is_empty()) {
+        let missing = keywords.missing.iter().take(MAX_SUGGESTED_KEYWORDS).cloned().collect::<Vec<_>>().join(", ");
+        suggestions.push(t_with(language, "ats_missing_keywords", &[("keywords", &missing)]));
+    }
+
+    Report { score, checks, keywords, suggestions }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AtsRequest {
+    /// Checks the CV tailored to this vacancy, or the main one if there is none, against its description.
+    vacancy_id: Option<i32>,
+    /// A job description to compare the main CV with.
+    description: Option<String>,
+}
+
+pub async fn ats_post(Path(id): Path<i32>, State(app_state): State<AppState>, Json(request): Json<AtsRequest>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let (html, description) = match request.vacancy_id {
+        Some(vacancy_id) => match db::load_vacancy(&app_state.pool, id, vacancy_id).await {
+            Ok(Some(vacancy)) => (vacancy.resume_html.or(user.get_resume_html().map(str::to_string)), Some(vacancy.description)),
+            _ => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => (
+            user.get_resume_html().map(str::to_string),
+            request.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+        ),
+    };
+    let Some(html) = html else {
+        return conflict(&t(user.get_language().as_deref(), "there is no CV to check yet"));
+    };
+
+    Json(analyse(&html, description.as_deref(), user.get_language().as_deref())).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A CV that passes every structure check, with `extra` added to its text.
+    fn cv(extra: &str) -> String {
+        let filler = "Built data pipelines in Rust and Kubernetes for a payments company. ".repeat(20);
+        format!(
+            "<h1>Jane Doe</h1><p>jane.doe@example.com, +1 (555) 123-4567</p>\
+             <h2>Summary</h2><p>Backend engineer.</p>\
+             <h2>Work Experience</h2><p>{filler}{extra}</p>\
+             <h2>Education</h2><p>MSc Computer Science</p>\
+             <h2>Skills</h2><ul><li>Rust</li><li>Kubernetes</li></ul>"
+        )
+    }
+
+    #[test]
+    fn headings_are_found_in_any_interview_language() {
+        let document = Document::parse("<h2>Berufserfahrung</h2><h3>Образование</h3><p>Skills</p>");
+        assert!(document.has_heading(EXPERIENCE_HEADINGS));
+        assert!(document.has_heading(EDUCATION_HEADINGS));
+        // Only headings count, not the same word in the text.
+        assert!(!document.has_heading(SKILLS_HEADINGS));
+    }
+
+    #[test]
+    fn email_needs_a_user_and_a_domain() {
+        assert!(Document::parse("<p>Mail: <a>jane.doe@example.com</a>.</p>").has_email());
+        assert!(!Document::parse("<p>@jane on GitHub</p>").has_email());
+        assert!(!Document::parse("<p>root@localhost</p>").has_email());
+    }
+
+    #[test]
+    fn phone_is_not_taken_from_dates() {
+        assert!(Document::parse("<p>+49 (30) 1234-5678</p>").has_phone());
+        assert!(!Document::parse("<p>2019-06 - 2023-01</p>").has_phone());
+        assert!(!Document::parse("<p>2019.06–2023.01</p>").has_phone());
+    }
+
+    #[test]
+    fn keywords_keep_the_most_frequent_in_order_of_appearance() {
+        let unique: Vec<String> = (0..30).map(|i| format!("w{i}")).collect();
+        let description = format!("{} rust rust rust", unique.join(" "));
+
+        let keywords = keywords(&description, &HashSet::new());
+        assert_eq!(keywords.len(), MAX_KEYWORDS);
+        assert_eq!(keywords.first().map(String::as_str), Some("w0"));
+        assert_eq!(keywords.last().map(String::as_str), Some("rust"));
+        assert!(!keywords.contains(&"w29".to_string()));
+    }
+
+    #[test]
+    fn stop_words_of_the_description_language_are_skipped() {
+        let english = "We are looking for an engineer with experience in Rust and Kubernetes who will work with our team.";
+        assert_eq!(keywords(english, &stop_words(english).unwrap()), ["engineer", "rust", "kubernetes"]);
+
+        let german = "Wir suchen eine Entwicklerin mit Erfahrung in Rust und Kubernetes für unser Team in Berlin.";
+        assert_eq!(keywords(german, &stop_words(german).unwrap()), ["entwicklerin", "rust", "kubernetes", "berlin"]);
+    }
+
+    #[test]
+    fn keyword_report_is_skipped_for_a_language_without_stop_words() {
+        let polish = "Szukamy doświadczonego programisty, który będzie pracował nad naszym systemem płatności w zespole.";
+        assert!(analyse(&cv(""), Some(polish), None).keywords.is_none());
+    }
+
+    #[test]
+    fn score_weighs_structure_and_keywords() {
+        let report = analyse(&cv(""), None, None);
+        assert!(report.checks.iter().all(|c| c.passed), "{:?}", report.checks);
+        assert_eq!(report.score, 100);
+        assert!(report.suggestions.is_empty());
+
+        // Half of the keywords are in the CV: 100 * 0.6 + 50 * 0.4.
+        let report = analyse(&cv(""), Some("Rust, Golang"), None);
+        let keywords = report.keywords.as_ref().unwrap();
+        assert_eq!(keywords.coverage, 50);
+        assert_eq!(keywords.matched, ["rust"]);
+        assert_eq!(keywords.missing, ["golang"]);
+        assert_eq!(report.score, 80);
+        assert!(report.suggestions[0].contains("golang"));
+    }
+
+    #[test]
+    fn failed_checks_lower_the_score_by_their_weight() {
+        let report = analyse(&cv("<table><tr><td>Rust</td></tr></table>"), None, None);
+        assert_eq!(report.score, 85);
+        assert_eq!(report.suggestions.len(), 1);
+    }
+
+    #[test]
+    fn suggestions_are_in_the_user_language() {
+        let report = analyse(&cv("<table><tr><td>Rust</td></tr></table>"), Some("Rust, Golang"), Some("de"));
+        assert!(report.suggestions[0].starts_with("Ersetze Tabellen"));
+        assert!(report.suggestions[1].ends_with(": golang."));
+    }
+}
@@ -0,0 +1,174 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use crate::db;
+use crate::dialogue::Dialogue;
+use crate::i18n::{t, t_with};
+use crate::reply::conflict;
+use crate::stage::Stage;
+use crate::storage::delete_unused;
+use crate::user::{Back, Mode, Progress, User};
+use crate::{dialogue_reply, get_bucket_name, new_asker, AppState, OpenAI};
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Reset,
+    ShowProgress,
+    SkipQuestion { index: Option<u8> },
+    Back,
+    Undo,
+    Regenerate {
+        open_ai: Option<OpenAI>,
+        max_tokens: Option<u32>,
+    },
+    ChangeProfession { profession: Option<String> },
+    SetMode { mode: Mode },
+    /// Has the model critique the CV and help improve the answers behind it.
+    Review {
+        open_ai: Option<OpenAI>,
+        max_tokens: Option<u32>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct CommandReply {
+    stage: Stage,
+    message: String,
+    /// A new CV is ready at `/users/:id/cv`.
+    generated: bool,
+    progress: Option<Progress>,
+}
+
+async fn delete_resume(app_state: &AppState, name: Option<String>) {
+    delete_unused(&app_state.s3_client, &get_bucket_name(), name).await;
+}
+
+/// Lets the model go on from the command without a message from the user.
+async fn continue_dialogue(app_state: &AppState, user: User, open_ai: Option<OpenAI>, max_tokens: Option<u32>) -> Response {
+    let asker = new_asker(app_state, user.get_tenant(), open_ai);
+    let dialogue = Dialogue::new(user, asker, None, max_tokens);
+    match dialogue_reply(app_state, dialogue).await {
+        Ok((generated, message, user)) => Json(CommandReply { stage: user.get_stage(), message, generated, progress: None }).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Commands are explicit actions on the dialogue, so free-text answers are never taken for one.
+pub async fn user_command(Path(id): Path<i32>, State(app_state): State<AppState>, Json(command): Json<Command>) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let language = user.get_language();
+    let language = language.as_deref();
+    let message = match command {
+        Command::Reset => {
+            match db::delete_vacancies(&app_state.pool, id).await {
+                Ok(resumes) => {
+                    for resume in resumes {
+                        delete_resume(&app_state, Some(resume)).await;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to delete the vacancies: {e:?}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            }
+            delete_resume(&app_state, user.get_resume()).await;
+            delete_resume(&app_state, user.get_cover_letter()).await;
+            delete_resume(&app_state, user.get_photo()).await;
+            user.reset("command");
+            t(language, "data_reset")
+        }
+        Command::ShowProgress => {
+            let progress = user.progress();
+            let message = t_with(language, "progress", &[
+                ("answered", &progress.answered.to_string()),
+                ("total", &progress.total.to_string()),
+            ]);
+            return Json(CommandReply { stage: user.get_stage(), message, generated: false, progress: Some(progress) }).into_response();
+        }
+        Command::SkipQuestion { index } => match user.skip_question(index) {
+            Ok(index) => t_with(language, "question_skipped", &[("index", &index.to_string())]),
+            Err(e) => return conflict(&t(language, e)),
+        },
+        Command::Back => match user.back() {
+            Ok(Back::Reopened(index, question)) => t_with(language, "question_reopened", &[
+                ("index", &index.to_string()),
+                ("question", &question),
+            ]),
+            Ok(Back::QuestionsDiscarded) => t(language, "questions_discarded"),
+            Ok(Back::ProfessionCleared) => t(language, "profession_cleared"),
+            Ok(Back::ReviewClosed) => t(language, "review_closed"),
+            Err(e) => return conflict(&t(language, e)),
+        },
+        Command::Undo => {
+            let snapshot = match db::load_last_snapshot(&app_state.pool, id).await {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => return conflict(&t(language, "there is nothing to undo")),
+                Err(e) => {
+                    error!("Failed to load snapshot: {e:?}");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+            let snapshot_id = snapshot.id;
+
+            let old_resume = user.undo(snapshot);
+            if user.save(&app_state.pool).await.is_err() {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+            if let Err(e) = db::delete_snapshot(&app_state.pool, snapshot_id).await {
+                error!("Failed to delete snapshot {snapshot_id}: {e:?}");
+            }
+            delete_resume(&app_state, old_resume).await;
+
+            let stage = t(language, &format!("stage_{}", user.get_stage().as_str()));
+            let message = t_with(language, "stage_restored", &[("stage", &stage)]);
+            return Json(CommandReply { stage: user.get_stage(), message, generated: false, progress: None }).into_response();
+        }
+        Command::ChangeProfession { profession } => match user.change_profession(profession.as_deref()) {
+            Ok(old_resume) => {
+                delete_resume(&app_state, old_resume).await;
+                match profession {
+                    Some(profession) => t_with(language, "profession_changed", &[("profession", &profession)]),
+                    None => t(language, "profession_ask"),
+                }
+            }
+            Err(e) => return conflict(&t(language, e)),
+        },
+        Command::SetMode { mode } => {
+            user.set_mode(mode);
+            let mode = t(language, &format!("mode_{}", mode.as_str()));
+            t_with(language, "mode_set", &[("mode", &mode)])
+        }
+        Command::Regenerate { open_ai, max_tokens } => {
+            match user.regenerate() {
+                Ok(old_resume) => delete_resume(&app_state, old_resume).await,
+                Err(e) => return conflict(&t(language, e)),
+            }
+
+            return continue_dialogue(&app_state, user, open_ai, max_tokens).await;
+        }
+        Command::Review { open_ai, max_tokens } => {
+            match user.get_stage() {
+                Stage::Done => {
+                    if let Err(e) = user.start_review() {
+                        return conflict(&t(language, e));
+                    }
+                }
+                Stage::Review => {}
+                _ => return conflict(&t(language, "there is no CV to review yet")),
+            }
+            return continue_dialogue(&app_state, user, open_ai, max_tokens).await;
+        }
+    };
+
+    if user.save(&app_state.pool).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    Json(CommandReply { stage: user.get_stage(), message, generated: false, progress: None }).into_response()
+}
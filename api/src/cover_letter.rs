@@ -0,0 +1,73 @@
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+use crate::db;
+use crate::dialogue::Dialogue;
+use crate::reply::{bad_gateway, conflict};
+use crate::stage::Stage;
+use crate::storage::{delete_unused, load};
+use crate::user::User;
+use crate::{get_bucket_name, new_asker, store_resume, AppState, OpenAI};
+
+#[derive(Debug, Deserialize)]
+pub struct CoverLetterRequest {
+    /// A vacancy attached before; takes precedence over `description`.
+    vacancy_id: Option<i32>,
+    /// A job description pasted just for this letter.
+    description: Option<String>,
+    open_ai: Option<OpenAI>,
+    max_tokens: Option<u32>,
+}
+
+/// Writes a cover letter from the current answers, replacing the one made before.
+pub async fn cover_letter_post(Path(id): Path<i32>, State(app_state): State<AppState>, Json(request): Json<CoverLetterRequest>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !matches!(user.get_stage(), Stage::Resume | Stage::Done | Stage::Review) {
+        return conflict("the survey isn't finished yet");
+    }
+    let vacancy = match request.vacancy_id {
+        Some(vacancy_id) => match db::load_vacancy(&app_state.pool, id, vacancy_id).await {
+            Ok(Some(vacancy)) => Some(vacancy.description),
+            _ => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => request.description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+    };
+
+    let asker = new_asker(&app_state, user.get_tenant(), request.open_ai);
+    let mut dialogue = Dialogue::new(user, asker, None, request.max_tokens);
+    let letter = dialogue.write_cover_letter(vacancy.as_deref()).await;
+    let (html, prompt_version) = match letter {
+        Ok(letter) => letter,
+        Err(e) => {
+            dialogue.save_user(&app_state.pool).await;
+            return bad_gateway(&e);
+        }
+    };
+
+    let name = store_resume(&app_state, &html, None).await;
+    let old_name = dialogue.set_cover_letter(&name, &prompt_version);
+    dialogue.save_user(&app_state.pool).await;
+    delete_unused(&app_state.s3_client, &get_bucket_name(), old_name).await;
+    Json(json!({ "generated": true })).into_response()
+}
+
+pub async fn cover_letter_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(name) = user.get_cover_letter() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let bytes = load(&app_state.s3_client, &get_bucket_name(), &name).await.expect("Failed s3_load");
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf; charset=utf-8"),
+        (header::CONTENT_DISPOSITION, "attachment; filename=\"cover-letter.pdf\""),
+    ];
+    (headers, bytes).into_response()
+}
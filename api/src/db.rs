@@ -1,8 +1,12 @@
 use std::env;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
 use sqlx::{Postgres, Pool, Error};
 use sqlx::postgres::PgPoolOptions;
 
-use crate::user::UserWithCustomMessages;
+use crate::stage::Transition;
+use crate::user::{Snapshot, UserWithCustomMessages};
 
 pub async fn create_pool() -> Pool<Postgres> {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -17,7 +21,7 @@ pub async fn load_user(pool: &Pool<Postgres>, id: i32) -> Result<Option<UserWith
     let query = sqlx::query_as!(
         UserWithCustomMessages,
         r#"
-        SELECT id, profession, questions, resume, messages, tokens_spent
+        SELECT id, tenant, stage, mode, language, cv_language, profession, questions, resume, resume_prompt_version, resume_html, cover_letter, cover_letter_prompt_version, photo, messages, summary, summarized_messages, tokens_spent, prompt_tokens_spent, completion_tokens_spent
         FROM users
         WHERE id = $1
         "#,
@@ -35,21 +39,49 @@ pub async fn load_user(pool: &Pool<Postgres>, id: i32) -> Result<Option<UserWith
 pub async fn save_user(pool: &Pool<Postgres>, user: UserWithCustomMessages) -> Result<(), &'static str> {
     let query = sqlx::query!(
         r#"
-        INSERT INTO users (id, profession, questions, resume, messages, tokens_spent)
-        VALUES ($1, $2, $3, $4, $5, $6)
+        INSERT INTO users (id, tenant, language, cv_language, profession, questions, resume, resume_prompt_version, messages, summary, summarized_messages, tokens_spent, prompt_tokens_spent, completion_tokens_spent, stage, mode, cover_letter, cover_letter_prompt_version, resume_html, photo)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
         ON CONFLICT (id) DO UPDATE
-        SET profession = EXCLUDED.profession,
+        SET tenant = EXCLUDED.tenant,
+            stage = EXCLUDED.stage,
+            mode = EXCLUDED.mode,
+            language = EXCLUDED.language,
+            cv_language = EXCLUDED.cv_language,
+            profession = EXCLUDED.profession,
             questions = EXCLUDED.questions,
             resume = EXCLUDED.resume,
+            resume_prompt_version = EXCLUDED.resume_prompt_version,
+            resume_html = EXCLUDED.resume_html,
+            cover_letter = EXCLUDED.cover_letter,
+            cover_letter_prompt_version = EXCLUDED.cover_letter_prompt_version,
+            photo = EXCLUDED.photo,
             messages = EXCLUDED.messages,
-            tokens_spent = EXCLUDED.tokens_spent
+            summary = EXCLUDED.summary,
+            summarized_messages = EXCLUDED.summarized_messages,
+            tokens_spent = EXCLUDED.tokens_spent,
+            prompt_tokens_spent = EXCLUDED.prompt_tokens_spent,
+            completion_tokens_spent = EXCLUDED.completion_tokens_spent
         "#,
         user.id,
+        user.tenant,
+        user.language,
+        user.cv_language,
         user.profession,
         user.questions,
         user.resume,
+        user.resume_prompt_version,
         user.messages,
+        user.summary,
+        user.summarized_messages,
         user.tokens_spent,
+        user.prompt_tokens_spent,
+        user.completion_tokens_spent,
+        user.stage,
+        user.mode,
+        user.cover_letter,
+        user.cover_letter_prompt_version,
+        user.resume_html,
+        user.photo,
     )
         .execute(pool)
         .await;
@@ -60,14 +92,16 @@ pub async fn save_user(pool: &Pool<Postgres>, user: UserWithCustomMessages) -> R
     }
 }
 
-pub async fn new_user(pool: &Pool<Postgres>) -> Result<u64, &'static str> {
+pub async fn new_user(pool: &Pool<Postgres>, tenant: &str, mode: &str) -> Result<u64, &'static str> {
     let rec = sqlx::query!(
         r#"
-        INSERT INTO users ( tokens_spent )
-        VALUES ( $1 )
+        INSERT INTO users ( tokens_spent, tenant, mode )
+        VALUES ( $1, $2, $3 )
         RETURNING id
         "#,
-        0
+        0,
+        tenant,
+        mode,
     )
         .fetch_one(pool)
         .await;
@@ -76,4 +110,404 @@ pub async fn new_user(pool: &Pool<Postgres>) -> Result<u64, &'static str> {
         Ok(rec) => Ok(rec.id as u64),
         Err(_) => Err("Failed to create new user"),
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize)]
+pub struct DialogueEvent {
+    pub id: i32,
+    pub from_stage: String,
+    pub to_stage: String,
+    pub trigger: String,
+    pub detail: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+pub async fn add_dialogue_events(pool: &Pool<Postgres>, user_id: i32, transitions: &[Transition]) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+
+    for transition in transitions {
+        sqlx::query!(
+            r#"
+            INSERT INTO dialogue_events (user_id, from_stage, to_stage, trigger, detail)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            user_id,
+            transition.from.as_str(),
+            transition.to.as_str(),
+            transition.trigger.as_str(),
+            transition.detail,
+        )
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await
+}
+
+pub async fn load_dialogue_events(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<DialogueEvent>, Error> {
+    sqlx::query_as!(
+        DialogueEvent,
+        r#"
+        SELECT id, from_stage, to_stage, trigger, detail, created
+        FROM dialogue_events
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+/// How many snapshots are kept per user; older ones can't be restored.
+const MAX_SNAPSHOTS: i64 = 20;
+
+#[derive(Debug)]
+pub struct DialogueSnapshot {
+    pub id: i32,
+    pub stage: String,
+    pub profession: Option<String>,
+    pub questions: Option<Value>,
+    pub messages: Value,
+    pub summary: Option<String>,
+    pub summarized_messages: i32,
+}
+
+pub async fn add_dialogue_snapshots(pool: &Pool<Postgres>, user_id: i32, snapshots: &[Snapshot]) -> Result<(), Error> {
+    let mut tx = pool.begin().await?;
+
+    for snapshot in snapshots {
+        sqlx::query!(
+            r#"
+            INSERT INTO dialogue_snapshots (user_id, stage, profession, questions, messages, summary, summarized_messages)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            user_id,
+            snapshot.stage,
+            snapshot.profession,
+            snapshot.questions,
+            snapshot.messages,
+            snapshot.summary,
+            snapshot.summarized_messages,
+        )
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query!(
+        r#"
+        DELETE FROM dialogue_snapshots
+        WHERE user_id = $1 AND id NOT IN (
+            SELECT id FROM dialogue_snapshots WHERE user_id = $1 ORDER BY id DESC LIMIT $2
+        )
+        "#,
+        user_id,
+        MAX_SNAPSHOTS,
+    )
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+pub async fn load_last_snapshot(pool: &Pool<Postgres>, user_id: i32) -> Result<Option<DialogueSnapshot>, Error> {
+    sqlx::query_as!(
+        DialogueSnapshot,
+        r#"
+        SELECT id, stage, profession, questions, messages, summary, summarized_messages
+        FROM dialogue_snapshots
+        WHERE user_id = $1
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn delete_snapshot(pool: &Pool<Postgres>, id: i32) -> Result<(), Error> {
+    sqlx::query!("DELETE FROM dialogue_snapshots WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct PromptVersion {
+    pub tenant: String,
+    pub name: String,
+    pub language: String,
+    pub version: i32,
+    pub text: String,
+    pub active: bool,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ToolVersion {
+    pub tenant: String,
+    pub name: String,
+    pub version: i32,
+    pub description: String,
+    pub parameters: Value,
+    pub active: bool,
+    pub created: DateTime<Utc>,
+}
+
+pub async fn load_active_prompts(pool: &Pool<Postgres>) -> Result<Vec<PromptVersion>, Error> {
+    sqlx::query_as!(
+        PromptVersion,
+        r#"
+        SELECT tenant, name, language, version, text, active, created
+        FROM prompts
+        WHERE active
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn load_prompt_versions(pool: &Pool<Postgres>, tenant: &str, name: &str, language: &str) -> Result<Vec<PromptVersion>, Error> {
+    sqlx::query_as!(
+        PromptVersion,
+        r#"
+        SELECT tenant, name, language, version, text, active, created
+        FROM prompts
+        WHERE tenant = $1 AND name = $2 AND language = $3
+        ORDER BY version DESC
+        "#,
+        tenant,
+        name,
+        language,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+/// Stores `text` as the next version of the prompt and makes it the active one.
+pub async fn add_prompt_version(pool: &Pool<Postgres>, tenant: &str, name: &str, language: &str, text: &str) -> Result<i32, Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE prompts SET active = FALSE WHERE tenant = $1 AND name = $2 AND language = $3", tenant, name, language)
+        .execute(&mut *tx)
+        .await?;
+
+    let version = sqlx::query_scalar!(
+        r#"
+        INSERT INTO prompts (tenant, name, language, version, text, active)
+        SELECT $1, $2, $3, COALESCE(MAX(version), 0) + 1, $4, TRUE
+        FROM prompts
+        WHERE tenant = $1 AND name = $2 AND language = $3
+        RETURNING version
+        "#,
+        tenant,
+        name,
+        language,
+        text,
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(version)
+}
+
+/// Activates the given version, or no version at all so the tenant falls back to the defaults.
+pub async fn activate_prompt_version(pool: &Pool<Postgres>, tenant: &str, name: &str, language: &str, version: Option<i32>) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE prompts SET active = FALSE WHERE tenant = $1 AND name = $2 AND language = $3", tenant, name, language)
+        .execute(&mut *tx)
+        .await?;
+
+    if let Some(version) = version {
+        let updated = sqlx::query!(
+            "UPDATE prompts SET active = TRUE WHERE tenant = $1 AND name = $2 AND language = $3 AND version = $4",
+            tenant,
+            name,
+            language,
+            version,
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn load_active_tools(pool: &Pool<Postgres>) -> Result<Vec<ToolVersion>, Error> {
+    sqlx::query_as!(
+        ToolVersion,
+        r#"
+        SELECT tenant, name, version, description, parameters, active, created
+        FROM tool_schemas
+        WHERE active
+        "#
+    )
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn load_tool_versions(pool: &Pool<Postgres>, tenant: &str, name: &str) -> Result<Vec<ToolVersion>, Error> {
+    sqlx::query_as!(
+        ToolVersion,
+        r#"
+        SELECT tenant, name, version, description, parameters, active, created
+        FROM tool_schemas
+        WHERE tenant = $1 AND name = $2
+        ORDER BY version DESC
+        "#,
+        tenant,
+        name,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+/// Stores the schema as the next version of the tool and makes it the active one.
+pub async fn add_tool_version(pool: &Pool<Postgres>, tenant: &str, name: &str, description: &str, parameters: &Value) -> Result<i32, Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE tool_schemas SET active = FALSE WHERE tenant = $1 AND name = $2", tenant, name)
+        .execute(&mut *tx)
+        .await?;
+
+    let version = sqlx::query_scalar!(
+        r#"
+        INSERT INTO tool_schemas (tenant, name, version, description, parameters, active)
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, TRUE
+        FROM tool_schemas
+        WHERE tenant = $1 AND name = $2
+        RETURNING version
+        "#,
+        tenant,
+        name,
+        description,
+        parameters,
+    )
+        .fetch_one(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(version)
+}
+
+/// Activates the given version, or no version at all so the tenant falls back to the defaults.
+pub async fn activate_tool_version(pool: &Pool<Postgres>, tenant: &str, name: &str, version: Option<i32>) -> Result<bool, Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!("UPDATE tool_schemas SET active = FALSE WHERE tenant = $1 AND name = $2", tenant, name)
+        .execute(&mut *tx)
+        .await?;
+
+    if let Some(version) = version {
+        let updated = sqlx::query!(
+            "UPDATE tool_schemas SET active = TRUE WHERE tenant = $1 AND name = $2 AND version = $3",
+            tenant,
+            name,
+            version,
+        )
+            .execute(&mut *tx)
+            .await?;
+
+        if updated.rows_affected() == 0 {
+            return Ok(false);
+        }
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Vacancy {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+    /// Name of the CV tailored to the vacancy in the bucket.
+    pub resume: Option<String>,
+    pub resume_prompt_version: Option<String>,
+    #[serde(skip)]
+    pub resume_html: Option<String>,
+    pub created: DateTime<Utc>,
+}
+
+pub async fn add_vacancy(pool: &Pool<Postgres>, user_id: i32, title: &str, description: &str) -> Result<Vacancy, Error> {
+    sqlx::query_as!(
+        Vacancy,
+        r#"
+        INSERT INTO vacancies (user_id, title, description)
+        VALUES ($1, $2, $3)
+        RETURNING id, title, description, resume, resume_prompt_version, resume_html, created
+        "#,
+        user_id,
+        title,
+        description,
+    )
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn load_vacancies(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<Vacancy>, Error> {
+    sqlx::query_as!(
+        Vacancy,
+        r#"
+        SELECT id, title, description, resume, resume_prompt_version, resume_html, created
+        FROM vacancies
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id,
+    )
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn load_vacancy(pool: &Pool<Postgres>, user_id: i32, id: i32) -> Result<Option<Vacancy>, Error> {
+    sqlx::query_as!(
+        Vacancy,
+        r#"
+        SELECT id, title, description, resume, resume_prompt_version, resume_html, created
+        FROM vacancies
+        WHERE user_id = $1 AND id = $2
+        "#,
+        user_id,
+        id,
+    )
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn set_vacancy_resume(pool: &Pool<Postgres>, id: i32, resume: &str, html: &str, prompt_version: &str) -> Result<(), Error> {
+    sqlx::query!(
+        "UPDATE vacancies SET resume = $2, resume_html = $3, resume_prompt_version = $4 WHERE id = $1",
+        id,
+        resume,
+        html,
+        prompt_version,
+    )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_vacancy(pool: &Pool<Postgres>, user_id: i32, id: i32) -> Result<(), Error> {
+    sqlx::query!("DELETE FROM vacancies WHERE user_id = $1 AND id = $2", user_id, id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Deletes all the user's vacancies; returns the file names of the CVs made for them.
+pub async fn delete_vacancies(pool: &Pool<Postgres>, user_id: i32) -> Result<Vec<String>, Error> {
+    let resumes = sqlx::query_scalar!("DELETE FROM vacancies WHERE user_id = $1 RETURNING resume", user_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(resumes.into_iter().flatten().collect())
+}
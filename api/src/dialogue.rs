@@ -1,7 +1,12 @@
 use async_openai::types::{ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs};
 use sqlx::{Pool, Postgres};
-use crate::ask::{Asker, Response};
-use crate::user::{Need, User};
+use tracing::{error, warn};
+use crate::ask::{AnswerCall, Asker, Response, ToolCallRequest};
+use crate::entry;
+use crate::i18n;
+use crate::tokens::{self, message_text};
+use crate::stage::Stage;
+use crate::user::{Mode, User};
 
 const MAX_HISTORY: usize = 5_000;
 const MAX_TOKENS: u32 = 50_000;
@@ -11,11 +16,15 @@ pub struct Dialogue {
     asker: Asker,
     max_history: usize,
     max_tokens: u32,
+    /// Tool calls made by the last step, as `name(arguments)`.
+    last_tool_calls: Vec<String>,
 }
 
 pub enum Instruction {
-    SaveResume,
-    DeleteResume(String),
+    /// Render and store the generated resume, made with the given prompt version.
+    SaveResume(String),
+    /// The user answers in bulk through `/users/:id/form` rather than in chat.
+    ShowForm,
     None,
 }
 
@@ -23,25 +32,46 @@ impl Dialogue {
     pub fn new(user: User, asker: Asker, max_history: Option<usize>, max_tokens: Option<u32>) -> Self {
         let max_history = max_history.unwrap_or(MAX_HISTORY);
         let max_tokens = max_tokens.unwrap_or(MAX_TOKENS);
-        Self { user, asker, max_history, max_tokens }
+        Self { user, asker, max_history, max_tokens, last_tool_calls: vec![] }
     }
 
-    pub async fn set_resume(&mut self, name: &str) -> Result<(), &'static str> {
-        self.user.set_resume(name);
-        Ok(())
+    pub fn user_id(&self) -> u64 {
+        self.user.id
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.user.get_stage()
+    }
+
+    pub fn photo(&self) -> Option<String> {
+        self.user.get_photo()
+    }
+
+    pub fn tokens_spent(&self) -> u32 {
+        self.user.get_tokens_spent()
+    }
+
+    pub fn last_tool_calls(&self) -> &[String] {
+        &self.last_tool_calls
+    }
+
+    pub fn detect_language(&mut self, language_code: Option<&str>, text: &str) {
+        self.user.detect_language(language_code, text);
+    }
+
+    pub async fn set_resume(&mut self, name: &str, html: &str, prompt_version: &str) -> Result<Option<String>, &'static str> {
+        self.user.set_resume(name, html, prompt_version)
+    }
+
+    /// Returns the file name of the cover letter it replaces.
+    pub fn set_cover_letter(&mut self, name: &str, prompt_version: &str) -> Option<String> {
+        self.user.set_cover_letter(name, prompt_version)
     }
 
     pub async fn process_message(&mut self, text: Option<&str>) -> (Option<String>, Instruction) {
-        if let Some(text) = text {
-            if text == "reset" {
-                let instruction = match self.user.get_resume() {
-                    Some(name) => Instruction::DeleteResume(name),
-                    None => Instruction::None
-                };
-                self.user.reset();
-                return (Some("Data reset".to_string()), instruction)
-            }
+        self.last_tool_calls.clear();
 
+        if let Some(text) = text {
             self.user.add_message(
                 ChatCompletionRequestUserMessageArgs::default()
                     .content(text)
@@ -51,30 +81,34 @@ impl Dialogue {
             )
         }
 
-        let messages = self.user.get_messages(Some(self.max_history));
-
-        match self.user.need() {
-            Need::None => {
-                (match text {
-                    Some(text) if text == "resume" => self.user.get_resume(),
-                    _ => Some("the end".to_string())
-                }, Instruction::None)
-            }
+        match self.user.get_stage() {
+            Stage::Answers if self.user.get_mode() == Mode::Form => (Some("form".to_string()), Instruction::ShowForm),
+            // The review is only started by its command, so a "thanks" doesn't cost a model call.
+            Stage::Done => (Some(self.text("cv_ready")), Instruction::None),
             others => {
                 if self.user.not_enough_tokens(self.max_tokens) {
-                    return (Some("Limit exceed".to_string()), Instruction::None);
+                    return (Some(self.text("limit_exceeded")), Instruction::None);
                 }
+                self.asker.set_token_budget(self.user.remaining_tokens(self.max_tokens));
+                self.asker.set_language(self.user.get_language());
+
+                self.compact_history().await;
+                let messages = self.user.get_messages(Some(self.max_history), &self.asker.model());
 
-                return match others {
-                    Need::None => unimplemented!(),
-                    Need::Profession => {
+                match others {
+                    Stage::Done => unreachable!("answered without the model above"),
+                    Stage::Profession => {
                         let payable_response = self.asker.get_profession(messages).await;
-                        self.user.add_tokens_spent(payable_response.tokens_spent);
+                        self.user.add_tokens_spent(&payable_response.usage);
                         (match payable_response.response {
                             Response::Profession(tool_call, profession) => {
-                                self.user.add_message(tool_call.request_message.unwrap());
+                                let request_message = tool_call.request_message.unwrap();
+                                self.last_tool_calls = tool_call_signatures(&request_message);
+                                self.user.add_message(request_message);
                                 self.user.add_func_success(&tool_call.call_id, &tool_call.function_name);
-                                self.user.set_profession(&profession);
+                                if let Err(e) = self.user.set_profession(&profession) {
+                                    warn!("Failed to save profession: {e}");
+                                }
                                 None
                             }
                             Response::Text(text) => {
@@ -87,17 +121,23 @@ impl Dialogue {
                                 );
                                 Some(text.to_string())
                             }
+                            Response::LimitExceeded => Some(self.text("limit_exceeded")),
+                            Response::Error(e) => Some(self.failed(&e)),
                             smt => panic!("Profession case _: {:?}", smt)
                         }, Instruction::None)
                     }
-                    Need::Questions => {
+                    Stage::Questions => {
                         let payable_response = self.asker.get_questions(messages).await;
-                        self.user.add_tokens_spent(payable_response.tokens_spent);
+                        self.user.add_tokens_spent(&payable_response.usage);
                         (match payable_response.response {
                             Response::Questions(tool_call, questions) => {
-                                self.user.add_message(tool_call.request_message.unwrap());
+                                let request_message = tool_call.request_message.unwrap();
+                                self.last_tool_calls = tool_call_signatures(&request_message);
+                                self.user.add_message(request_message);
                                 self.user.add_func_success(&tool_call.call_id, &tool_call.function_name);
-                                self.user.set_questions(questions);
+                                if let Err(e) = self.user.set_questions(questions) {
+                                    warn!("Failed to save questions: {e}");
+                                }
                                 None
                             }
                             Response::Text(text) => {
@@ -110,22 +150,20 @@ impl Dialogue {
                                 );
                                 Some(text.to_string())
                             }
-                            Response::Error(text) => panic!("Error case {:?}", text),
+                            Response::LimitExceeded => Some(self.text("limit_exceeded")),
+                            Response::Error(e) => Some(self.failed(&e)),
                             smt => panic!("Questions case _: {:?}", smt)
                         }, Instruction::None)
                     }
-                    Need::Answers => {
+                    Stage::Answers => {
                         let payable_response = self.asker.get_answers(self.answer_with_messages(messages)).await;
-                        self.user.add_tokens_spent(payable_response.tokens_spent);
+                        self.user.add_tokens_spent(&payable_response.usage);
                         (match payable_response.response {
                             Response::Answers(
                                 func_request_message, answers
                             ) => {
-                                self.user.add_message(func_request_message);
-                                for (tool_call, (index, answer)) in answers {
-                                    self.user.add_func_success(&tool_call.call_id, &tool_call.function_name);
-                                    self.user.set_answer(index, &answer);
-                                }
+                                self.last_tool_calls = tool_call_signatures(&func_request_message);
+                                self.apply_answers(func_request_message, answers);
                                 None
                             }
                             Response::Text(text) => {
@@ -138,20 +176,56 @@ impl Dialogue {
                                 );
                                 Some(text.to_string())
                             }
-                            Response::Error(text) => panic!("Error case {:?}", text),
+                            Response::LimitExceeded => Some(self.text("limit_exceeded")),
+                            Response::Error(e) => Some(self.failed(&e)),
                             smt => panic!("Answers case _: {:?}", smt)
                         }, Instruction::None)
                     }
-                    Need::Resume => {
-                        let payable_response = self.asker.clone_with_max_tokens(
+                    Stage::Review => {
+                        let payable_response = self.asker.get_review(self.review_with_messages(messages)).await;
+                        self.user.add_tokens_spent(&payable_response.usage);
+                        (match payable_response.response {
+                            Response::Review(func_request_message, answers, regenerate) => {
+                                self.last_tool_calls = tool_call_signatures(&func_request_message);
+                                self.apply_answers(func_request_message, answers);
+                                if let Some((tool_call, changes)) = regenerate {
+                                    match self.user.finish_review(&changes) {
+                                        Ok(()) => self.user.add_func_success(&tool_call.call_id, &tool_call.function_name),
+                                        Err(e) => self.user.add_func_error(&tool_call.call_id, e),
+                                    }
+                                }
+                                None
+                            }
+                            Response::Text(text) => {
+                                self.user.add_message(
+                                    ChatCompletionRequestMessage::Assistant(
+                                        ChatCompletionRequestAssistantMessageArgs::default()
+                                            .content(&text)
+                                            .build().unwrap()
+                                    )
+                                );
+                                Some(text.to_string())
+                            }
+                            Response::LimitExceeded => Some(self.text("limit_exceeded")),
+                            Response::Error(e) => Some(self.failed(&e)),
+                            smt => panic!("Review case _: {:?}", smt)
+                        }, Instruction::None)
+                    }
+                    Stage::Resume => {
+                        let mut asker = self.asker.clone_with_max_tokens(
                             4_000   // TODO better
-                        ).get_resume(self.answer_with_messages(vec![])).await;
-                        self.user.add_tokens_spent(payable_response.tokens_spent);
+                        );
+                        asker.set_language(self.user.get_cv_language());
+                        let payable_response = asker.get_resume(self.answer_with_messages(vec![])).await;
+                        self.user.add_tokens_spent(&payable_response.usage);
                         match payable_response.response {
                             Response::Resume(tool_call, resume) => {
-                                self.user.add_message(tool_call.request_message.unwrap());
+                                let request_message = tool_call.request_message.unwrap();
+                                self.last_tool_calls = tool_call_signatures(&request_message);
+                                self.user.add_message(request_message);
                                 self.user.add_func_success(&tool_call.call_id, &tool_call.function_name);
-                                (Some(resume), Instruction::SaveResume)
+                                let resume = entry::render(&resume, self.user.get_questions());
+                                (Some(resume), Instruction::SaveResume(payable_response.prompt_version))
                             }
                             Response::Text(text) => {
                                 self.user.add_message(
@@ -163,14 +237,132 @@ impl Dialogue {
                                 );
                                 (Some(text.to_string()), Instruction::None)
                             }
+                            Response::LimitExceeded => (Some(self.text("limit_exceeded")), Instruction::None),
+                            Response::Error(e) => (Some(self.failed(&e)), Instruction::None),
                             smt => panic!("Resume case _: {:?}", smt)
                         }
                     }
-                };
+                }
             }
         }
     }
 
+    /// Fills in the survey from the text of the user's existing CV; returns how many calls were saved.
+    /// The CV itself stays out of the history, only what was taken from it gets there.
+    pub async fn import_cv(&mut self, cv: &str) -> Result<usize, String> {
+        if self.user.not_enough_tokens(self.max_tokens) {
+            return Err(self.text("limit_exceeded"));
+        }
+        self.asker.set_token_budget(self.user.remaining_tokens(self.max_tokens));
+        self.asker.set_language(self.user.get_language());
+
+        let messages = self.answer_with_messages(vec![
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(format!("My current CV:\n\n{cv}"))
+                .build()
+                .expect("failed build ChatCompletionRequestUserMessageArgs")
+                .into()
+        ]);
+        let payable_response = self.asker.get_imported_answers(messages).await;
+        self.user.add_tokens_spent(&payable_response.usage);
+        match payable_response.response {
+            Response::Answers(func_request_message, answers) => {
+                self.user.add_note("The user imported their existing CV. Answers taken from it are saved below; ask only about what is still missing.");
+                Ok(self.apply_answers(func_request_message, answers))
+            }
+            Response::Text(_) => Ok(0),
+            Response::LimitExceeded => Err(self.text("limit_exceeded")),
+            Response::Error(e) => Err(self.failed(&e)),
+            smt => panic!("Import case _: {:?}", smt)
+        }
+    }
+
+    /// Writes a CV for the vacancy from the current answers; returns its HTML and the prompt version.
+    /// The vacancy stays out of the history, as the interview doesn't depend on it.
+    pub async fn tailor_resume(&mut self, vacancy: &str) -> Result<(String, String), String> {
+        if self.user.not_enough_tokens(self.max_tokens) {
+            return Err(self.text("limit_exceeded"));
+        }
+        let asker = self.document_asker();
+        let messages = self.answer_with_messages(vec![vacancy_message(vacancy)]);
+        let payable_response = asker.get_tailored_resume(messages).await;
+        self.user.add_tokens_spent(&payable_response.usage);
+        match payable_response.response {
+            Response::Resume(_, resume) => Ok((entry::render(&resume, self.user.get_questions()), payable_response.prompt_version)),
+            Response::Text(_) => Err(self.failed("no resume in the reply")),
+            Response::LimitExceeded => Err(self.text("limit_exceeded")),
+            Response::Error(e) => Err(self.failed(&e)),
+            smt => panic!("Tailor case _: {:?}", smt)
+        }
+    }
+
+    /// Writes a cover letter from the current answers, aimed at the vacancy if one is given;
+    /// returns its HTML and the prompt version. Like a tailored CV, it stays out of the history.
+    pub async fn write_cover_letter(&mut self, vacancy: Option<&str>) -> Result<(String, String), String> {
+        if self.user.not_enough_tokens(self.max_tokens) {
+            return Err(self.text("limit_exceeded"));
+        }
+        let asker = self.document_asker();
+        let messages = self.answer_with_messages(vacancy.map(vacancy_message).into_iter().collect());
+        let payable_response = asker.get_cover_letter(messages).await;
+        self.user.add_tokens_spent(&payable_response.usage);
+        match payable_response.response {
+            Response::CoverLetter(letter) => Ok((letter, payable_response.prompt_version)),
+            Response::Text(_) => Err(self.failed("no cover letter in the reply")),
+            Response::LimitExceeded => Err(self.text("limit_exceeded")),
+            Response::Error(e) => Err(self.failed(&e)),
+            smt => panic!("Cover letter case _: {:?}", smt)
+        }
+    }
+
+    /// An asker for a whole document in the CV language, within the user's remaining budget.
+    fn document_asker(&self) -> Asker {
+        let mut asker = self.asker.clone_with_max_tokens(
+            4_000   // TODO better
+        );
+        asker.set_token_budget(self.user.remaining_tokens(self.max_tokens));
+        asker.set_language(self.user.get_cv_language());
+        asker
+    }
+
+    /// Saves answers and entries, telling the model about each call that had no effect.
+    fn apply_answers(&mut self, func_request_message: ChatCompletionRequestMessage, answers: Vec<(ToolCallRequest, AnswerCall)>) -> usize {
+        self.user.add_message(func_request_message);
+        let mut saved = 0;
+        for (tool_call, answer) in answers {
+            let result = match answer {
+                AnswerCall::Answer(index, answer) => self.user.set_answer(index, &answer),
+                AnswerCall::Entry(index, entry) => self.user.add_entry(index, entry),
+                AnswerCall::Invalid(e) => Some(e),
+            };
+            match result {
+                None => {
+                    self.user.add_func_success(&tool_call.call_id, &tool_call.function_name);
+                    saved += 1;
+                }
+                Some(e) => {
+                    warn!("Failed to apply {}: {e}", tool_call.function_name);
+                    self.user.add_func_error(&tool_call.call_id, e);
+                }
+            }
+        }
+        saved
+    }
+
+    /// Folds the oldest turns into the user's summary once the history no longer fits `max_history`.
+    async fn compact_history(&mut self) {
+        let Some(messages) = self.user.messages_to_compact(self.max_history, &self.asker.model()) else {
+            return;
+        };
+
+        let payable_response = self.asker.get_summary(self.user.get_summary(), transcript(&messages)).await;
+        self.user.add_tokens_spent(&payable_response.usage);
+        match payable_response.response {
+            Response::Text(summary) => self.user.set_summary(&summary, messages.len()),
+            smt => warn!("Failed to summarise history: {:?}", smt),
+        }
+    }
+
     pub async fn save_user(&mut self, pool: &Pool<Postgres>) {
         self.user.save(pool).await.expect("dialogue user save failed")
     }
@@ -191,15 +383,78 @@ impl Dialogue {
         }
     }
 
-    pub fn get_max_message_length(&self) -> usize {
-        return self.max_history
+    /// The answers and the CV made from them, so the review can point at both.
+    fn review_with_messages(&self, messages: Vec<ChatCompletionRequestMessage>) -> Vec<ChatCompletionRequestMessage> {
+        let cv = match self.user.get_resume_html() {
+            Some(html) => format!("The CV made from these answers:\n\n{html}"),
+            None => "The CV is not available, review the answers it was made from.".to_string(),
+        };
+        self.answer_with_messages(merge_messages(
+            vec![
+                ChatCompletionRequestMessage::System(
+                    ChatCompletionRequestSystemMessageArgs::default()
+                        .content(cv)
+                        .build().unwrap()
+                )
+            ],
+            messages,
+        ))
+    }
+
+    /// Reply in the user's language.
+    pub fn text(&self, key: &str) -> String {
+        i18n::t(self.user.get_language().as_deref(), key)
+    }
+
+    /// Reply for a request the model couldn't complete; the user's message stays in the history to retry.
+    fn failed(&self, error: &str) -> String {
+        error!("Failed to get a response: {error}");
+        self.text("failed")
+    }
+
+    /// A message must fit the history window, which is counted in tokens.
+    pub fn is_too_long(&self, text: &str) -> bool {
+        tokens::count_text(&self.asker.model(), text) > self.max_history
+    }
+}
+
+fn vacancy_message(vacancy: &str) -> ChatCompletionRequestMessage {
+    ChatCompletionRequestUserMessageArgs::default()
+        .content(format!("Job description:\n\n{vacancy}"))
+        .build()
+        .expect("failed build ChatCompletionRequestUserMessageArgs")
+        .into()
+}
+
+fn tool_call_signatures(message: &ChatCompletionRequestMessage) -> Vec<String> {
+    match message {
+        ChatCompletionRequestMessage::Assistant(am) => am.tool_calls.iter().flatten()
+            .map(|tc| format!("{}({})", tc.function.name, tc.function.arguments))
+            .collect(),
+        _ => vec![],
     }
 }
 
 fn merge_messages(messages0: Vec<ChatCompletionRequestMessage>, messages1: Vec<ChatCompletionRequestMessage>) -> Vec<ChatCompletionRequestMessage> {
     let mut merged_messages = Vec::with_capacity(messages0.len() + messages1.len());
-    merged_messages.extend(messages0.into_iter());
-    merged_messages.extend(messages1.into_iter());
+    merged_messages.extend(messages0);
+    merged_messages.extend(messages1);
     merged_messages
 }
 
+fn transcript(messages: &[ChatCompletionRequestMessage]) -> String {
+    messages.iter()
+        .map(|m| match m {
+            ChatCompletionRequestMessage::System(_) => format!("system: {}", message_text(m)),
+            ChatCompletionRequestMessage::User(_) => format!("user: {}", message_text(m)),
+            ChatCompletionRequestMessage::Assistant(am) => {
+                let calls = am.tool_calls.iter().flatten()
+                    .map(|tc| format!("\nassistant called {}({})", tc.function.name, tc.function.arguments))
+                    .collect::<String>();
+                format!("assistant: {}{calls}", message_text(m))
+            }
+            ChatCompletionRequestMessage::Tool(_) | ChatCompletionRequestMessage::Function(_) => format!("tool: {}", message_text(m)),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
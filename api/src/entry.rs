@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::user::Question;
+
+/// Where the CV gets the rendered work history; the model writes the section heading itself.
+pub const WORK_HISTORY_PLACEHOLDER: &str = "{{work_history}}";
+pub const EDUCATION_PLACEHOLDER: &str = "{{education}}";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Job {
+    pub employer: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    /// None while the user still works there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub achievements: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Study {
+    pub institution: String,
+    pub degree: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+}
+
+/// JSON Schema of a `Job`, shared by the `add_job` tool and the form.
+pub fn job_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "employer": { "type": "string", "minLength": 1, "description": "Company or organisation" },
+            "title": { "type": "string", "minLength": 1, "description": "Job title" },
+            "start": { "type": "string", "description": "Start date, e.g. 2020-06" },
+            "end": { "type": "string", "description": "End date; leave out for the current job" },
+            "achievements": {
+                "type": "array",
+                "items": { "type": "string" },
+                "description": "Key responsibilities and achievements",
+            },
+        },
+        "required": ["employer", "title"],
+        "additionalProperties": false,
+    })
+}
+
+/// JSON Schema of a `Study`, shared by the `add_education` tool and the form.
+pub fn study_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "institution": { "type": "string", "minLength": 1, "description": "University, school or course provider" },
+            "degree": { "type": "string", "description": "Degree, qualification or course" },
+            "start": { "type": "string", "description": "Start date, e.g. 2016-09" },
+            "end": { "type": "string", "description": "Graduation date" },
+        },
+        "required": ["institution", "degree"],
+        "additionalProperties": false,
+    })
+}
+
+/// One item of a repeatable answer, such as a job or a degree.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Entry {
+    Job(Job),
+    Study(Study),
+}
+
+impl Entry {
+    /// One line for the survey answer, which the model and the form show.
+    pub fn summary(&self) -> String {
+        let (what, dates) = match self {
+            Entry::Job(job) => (format!("{} at {}", job.title, job.employer), period(&job.start, &job.end)),
+            Entry::Study(study) if study.degree.is_empty() => (study.institution.clone(), period(&study.start, &study.end)),
+            Entry::Study(study) => (format!("{}, {}", study.degree, study.institution), period(&study.start, &study.end)),
+        };
+        match dates.is_empty() {
+            true => what,
+            false => format!("{what} ({dates})"),
+        }
+    }
+
+    fn to_html(&self) -> String {
+        match self {
+            Entry::Job(job) => {
+                let achievements = match job.achievements.is_empty() {
+                    true => String::new(),
+                    false => format!(
+                        "<ul>{}</ul>",
+                        job.achievements.iter().map(|a| format!("<li>{}</li>", escape(a))).collect::<String>(),
+                    ),
+                };
+                format!(
+                    "<p><strong>{}</strong></p><p><em>{}</em>{}</p>{achievements}",
+                    escape(&job.title), escape(&job.employer), dates_html(&job.start, &job.end),
+                )
+            }
+            Entry::Study(study) => format!(
+                "<p><strong>{}</strong></p><p><em>{}</em>{}</p>",
+                escape(&study.degree), escape(&study.institution), dates_html(&study.start, &study.end),
+            ),
+        }
+    }
+}
+
+/// The survey answer for a list of entries; none without entries.
+pub fn answer(entries: &[Entry]) -> Option<String> {
+    match entries.is_empty() {
+        true => None,
+        false => Some(entries.iter().map(Entry::summary).collect::<Vec<_>>().join("; ")),
+    }
+}
+
+fn period(start: &Option<String>, end: &Option<String>) -> String {
+    match (start, end) {
+        (Some(start), Some(end)) => format!("{start} - {end}"),
+        (Some(start), None) => format!("{start} - Present"),
+        (None, Some(end)) => end.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+fn dates_html(start: &Option<String>, end: &Option<String>) -> String {
+    match period(start, end) {
+        dates if dates.is_empty() => String::new(),
+        dates => format!(" - <em>{}</em>", escape(&dates)),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Puts the stored entries where the model left placeholders, so they reach the CV exactly as collected.
+pub fn render(html: &str, questions: &[Question]) -> String {
+    let jobs = questions.iter()
+        .flat_map(|q| q.entries())
+        .filter(|e| matches!(e, Entry::Job(_)))
+        .map(Entry::to_html)
+        .collect::<String>();
+    let studies = questions.iter()
+        .flat_map(|q| q.entries())
+        .filter(|e| matches!(e, Entry::Study(_)))
+        .map(Entry::to_html)
+        .collect::<String>();
+
+    html.replace(WORK_HISTORY_PLACEHOLDER, &jobs)
+        .replace(EDUCATION_PLACEHOLDER, &studies)
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use crate::dialogue::Dialogue;
+use crate::entry::{job_schema, study_schema, Entry};
+use crate::reply::{conflict, validation_error};
+use crate::user::{AnswerType, FormAnswer, Question, Section, User};
+use crate::{dialogue_reply, new_asker, AppState, OpenAI};
+
+/// Answers to list questions are stored as one line.
+const LIST_SEPARATOR: &str = "; ";
+
+#[derive(Debug, Deserialize)]
+pub struct FormSubmission {
+    /// Answers by question index; questions left out are skipped.
+    answers: Map<String, Value>,
+    open_ai: Option<OpenAI>,
+    max_tokens: Option<u32>,
+}
+
+fn question_schema(question: &Question) -> Value {
+    let mut schema = match question.answer_type() {
+        AnswerType::Text => json!({ "type": "string" }),
+        AnswerType::Date => json!({ "type": "string", "format": "date" }),
+        AnswerType::List => json!({ "type": "array", "items": { "type": "string" } }),
+        AnswerType::Select => json!({ "type": "string", "enum": question.options() }),
+        AnswerType::WorkHistory => json!({ "type": "array", "items": job_schema() }),
+        AnswerType::Education => json!({ "type": "array", "items": study_schema() }),
+    };
+    schema["title"] = json!(question.question());
+    if let Some(hint) = question.hint() {
+        schema["description"] = json!(hint);
+    }
+    if question.required() {
+        match question.answer_type() {
+            AnswerType::List | AnswerType::WorkHistory | AnswerType::Education => schema["minItems"] = json!(1),
+            _ => {
+                schema["minLength"] = json!(1);
+                schema["pattern"] = json!("\\S");
+            }
+        }
+    }
+    schema
+}
+
+/// JSON Schema of the form, one property per question keyed by its index.
+fn form_schema(questions: &[Question]) -> Value {
+    let properties: Map<String, Value> = questions.iter()
+        .map(|q| (q.index().to_string(), question_schema(q)))
+        .collect();
+    let required: Vec<String> = questions.iter()
+        .filter(|q| q.required())
+        .map(|q| q.index().to_string())
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Question indexes by section, in the order the form shows them.
+fn form_sections(questions: &[Question]) -> Vec<Value> {
+    let mut sections: Vec<(Section, Vec<u8>)> = vec![];
+    for question in questions {
+        match sections.iter_mut().find(|(s, _)| *s == question.section()) {
+            Some((_, indexes)) => indexes.push(question.index()),
+            None => sections.push((question.section(), vec![question.index()])),
+        }
+    }
+    sections.into_iter()
+        .map(|(section, questions)| json!({ "section": section, "questions": questions }))
+        .collect()
+}
+
+fn form_values(questions: &[Question]) -> Map<String, Value> {
+    questions.iter()
+        .filter_map(|q| {
+            let answer = q.answer()?;
+            let value = match q.answer_type() {
+                AnswerType::List => json!(answer.split(LIST_SEPARATOR).collect::<Vec<_>>()),
+                AnswerType::WorkHistory | AnswerType::Education => json!(q.entries()),
+                _ => json!(answer),
+            };
+            Some((q.index().to_string(), value))
+        })
+        .collect()
+}
+
+fn validate(schema: &Value, answers: &Value) -> Result<(), Vec<String>> {
+    let schema = jsonschema::JSONSchema::compile(schema)
+        .map_err(|e| vec![format!("form schema is invalid: {e}")])?;
+
+    let errors: Vec<String> = match schema.validate(answers) {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{path}: {e}"),
+            })
+            .collect(),
+    };
+    Err(errors)
+}
+
+/// Empty answers count as skipped.
+fn form_answers(answers: Map<String, Value>) -> BTreeMap<u8, FormAnswer> {
+    answers.into_iter()
+        .filter_map(|(index, value)| {
+            let answer = match value {
+                Value::String(s) => FormAnswer::Text(s.trim().to_string()),
+                Value::Array(items) if items.iter().all(Value::is_object) => FormAnswer::Entries(
+                    items.into_iter().filter_map(|i| serde_json::from_value::<Entry>(i).ok()).collect(),
+                ),
+                Value::Array(items) => FormAnswer::Text(
+                    items.iter()
+                        .filter_map(|i| i.as_str().map(str::trim))
+                        .filter(|i| !i.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(LIST_SEPARATOR),
+                ),
+                _ => return None,
+            };
+            let empty = match &answer {
+                FormAnswer::Text(text) => text.is_empty(),
+                FormAnswer::Entries(entries) => entries.is_empty(),
+            };
+            (!empty).then_some((index.parse().ok()?, answer))
+        })
+        .collect()
+}
+
+/// Required questions left blank once the answers are trimmed, which the schema alone can't tell.
+fn blank_required(questions: &[Question], answers: &BTreeMap<u8, FormAnswer>) -> Vec<String> {
+    questions.iter()
+        .filter(|q| q.required() && !answers.contains_key(&q.index()))
+        .map(|q| format!("/{}: a required answer can't be blank", q.index()))
+        .collect()
+}
+
+pub async fn form_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let questions = user.get_questions();
+    Json(json!({
+        "stage": user.get_stage(),
+        "schema": form_schema(questions),
+        "sections": form_sections(questions),
+        "values": form_values(questions),
+    })).into_response()
+}
+
+/// Saves all answers at once and, once every question is dealt with, generates the CV.
+pub async fn form_post(Path(id): Path<i32>, State(app_state): State<AppState>, Json(form): Json<FormSubmission>) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    if user.get_questions().is_empty() {
+        return conflict("there is no form to fill in right now");
+    }
+    if let Err(errors) = validate(&form_schema(user.get_questions()), &Value::Object(form.answers.clone())) {
+        return validation_error(errors);
+    }
+
+    let answers = form_answers(form.answers);
+    let blank = blank_required(user.get_questions(), &answers);
+    if !blank.is_empty() {
+        return validation_error(blank);
+    }
+
+    if let Err(e) = user.submit_form(answers) {
+        return conflict(e);
+    }
+    if user.save(&app_state.pool).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let asker = new_asker(&app_state, user.get_tenant(), form.open_ai);
+    let dialogue = Dialogue::new(user, asker, None, form.max_tokens);
+    match dialogue_reply(&app_state, dialogue).await {
+        Ok((generated, message, user)) => Json(json!({ "stage": user.get_stage(), "message": message, "generated": generated })).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn questions() -> Vec<Question> {
+        serde_json::from_value(json!([
+            { "index": 0, "question": "Full name", "type": "text", "required": true },
+            { "index": 1, "question": "Skills", "type": "list", "section": "skills", "required": true },
+            { "index": 2, "question": "Seniority", "type": "select", "options": ["junior", "senior"], "required": false },
+            { "index": 3, "question": "Work history", "type": "work_history", "section": "experience", "required": true },
+        ])).unwrap()
+    }
+
+    fn answers(answers: Value) -> Map<String, Value> {
+        answers.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn schema_has_a_property_per_question() {
+        let schema = form_schema(&questions());
+        assert_eq!(schema["required"], json!(["0", "1", "3"]));
+        assert_eq!(schema["properties"]["0"]["title"], "Full name");
+        assert_eq!(schema["properties"]["1"]["minItems"], 1);
+        assert_eq!(schema["properties"]["2"]["enum"], json!(["junior", "senior"]));
+        assert!(schema["properties"]["2"].get("minLength").is_none());
+        assert_eq!(schema["properties"]["3"]["items"], job_schema());
+    }
+
+    #[test]
+    fn schema_rejects_wrong_and_blank_answers() {
+        let schema = form_schema(&questions());
+        let job = json!({ "employer": "Acme", "title": "Data Engineer" });
+
+        assert!(validate(&schema, &json!({ "0": "Jane Doe", "1": ["Rust"], "3": [job] })).is_ok());
+        assert!(validate(&schema, &json!({ "0": "   ", "1": ["Rust"], "3": [job] })).is_err());
+        assert!(validate(&schema, &json!({ "0": "Jane Doe", "1": ["Rust"], "2": "lead", "3": [job] })).is_err());
+        assert!(validate(&schema, &json!({ "0": "Jane Doe", "1": [], "3": [{ "employer": "Acme" }] })).unwrap_err().len() >= 2);
+        assert!(validate(&schema, &json!({ "0": "Jane Doe", "1": ["Rust"], "3": [job], "9": "extra" })).is_err());
+    }
+
+    #[test]
+    fn answers_are_trimmed_and_lists_joined() {
+        let answers = form_answers(answers(json!({
+            "0": "  Jane Doe ",
+            "1": ["Rust", " ", "Kafka "],
+            "3": [{ "employer": "Acme", "title": "Data Engineer" }],
+        })));
+
+        assert!(matches!(&answers[&0], FormAnswer::Text(text) if text == "Jane Doe"));
+        assert!(matches!(&answers[&1], FormAnswer::Text(text) if text == "Rust; Kafka"));
+        assert!(matches!(&answers[&3], FormAnswer::Entries(entries) if entries.len() == 1));
+    }
+
+    #[test]
+    fn empty_answers_count_as_skipped() {
+        let answers = form_answers(answers(json!({ "0": " ", "1": [], "2": null, "x": "Jane" })));
+        assert!(answers.is_empty());
+    }
+
+    #[test]
+    fn blank_required_answers_are_reported() {
+        let questions = questions();
+        let answers = form_answers(answers(json!({ "0": "  ", "1": ["Rust"], "2": "senior" })));
+        assert_eq!(blank_required(&questions, &answers), [
+            "/0: a required answer can't be blank",
+            "/3: a required answer can't be blank",
+        ]);
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// Message catalogues, one JSON object of `key: text` per language. Errors of the dialogue are
+/// keyed by their English text, so the English catalogue leaves them out.
+const CATALOGUES: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.json")),
+    ("ru", include_str!("locales/ru.json")),
+    ("uk", include_str!("locales/uk.json")),
+    ("de", include_str!("locales/de.json")),
+    ("es", include_str!("locales/es.json")),
+];
+
+type Catalogue = HashMap<String, String>;
+
+fn catalogues() -> &'static HashMap<&'static str, Catalogue> {
+    static CATALOGUES_CELL: OnceLock<HashMap<&'static str, Catalogue>> = OnceLock::new();
+    CATALOGUES_CELL.get_or_init(|| {
+        CATALOGUES.iter()
+            .map(|(language, json)| {
+                let catalogue = serde_json::from_str(json)
+                    .unwrap_or_else(|e| panic!("Invalid message catalogue \"{language}\": {e}"));
+                (*language, catalogue)
+            })
+            .collect()
+    })
+}
+
+/// Text for `key` in the user's language, falling back to English and then to the key itself.
+pub fn t(language: Option<&str>, key: &str) -> String {
+    let catalogues = catalogues();
+    [language.unwrap_or(DEFAULT_LANGUAGE), DEFAULT_LANGUAGE].into_iter()
+        .find_map(|l| catalogues.get(l).and_then(|c| c.get(key)))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Like [`t`], substituting `{name}` placeholders.
+pub fn t_with(language: Option<&str>, key: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(t(language, key), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use axum::body::Bytes;
+use axum::extract::{Multipart, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::json;
+use tracing::warn;
+use crate::dialogue::Dialogue;
+use crate::profile::Profile;
+use crate::reply::{bad_gateway, conflict, unprocessable};
+use crate::stage::Stage;
+use crate::user::User;
+use crate::{dialogue_reply, new_asker, AppState};
+
+/// Uploads bigger than this are rejected before they are read.
+pub const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+/// A document longer than this is cut, as the rest rarely holds anything the survey or a vacancy needs.
+const MAX_TEXT_CHARS: usize = 20_000;
+
+const DOCX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Pdf,
+    Docx,
+    Text,
+    /// A LinkedIn "Download your data" archive.
+    LinkedIn,
+    JsonResume,
+}
+
+impl Format {
+    /// By the declared content type or file extension, falling back to the file's signature.
+    fn detect(content_type: Option<&str>, file_name: Option<&str>, bytes: &[u8]) -> Option<Self> {
+        let extension = file_name
+            .and_then(|n| n.rsplit_once('.'))
+            .map(|(_, e)| e.to_lowercase());
+        match (content_type, extension.as_deref()) {
+            (Some("application/pdf"), _) | (_, Some("pdf")) => Some(Format::Pdf),
+            (Some(DOCX_CONTENT_TYPE), _) | (_, Some("docx")) => Some(Format::Docx),
+            (Some("application/zip"), _) | (_, Some("zip")) => Some(Format::LinkedIn),
+            (Some("application/json"), _) | (_, Some("json")) => Some(Format::JsonResume),
+            (Some("text/plain" | "text/markdown"), _) | (_, Some("txt" | "md")) => Some(Format::Text),
+            _ if bytes.starts_with(b"%PDF") => Some(Format::Pdf),
+            _ if bytes.starts_with(b"PK\x03\x04") => match zip::ZipArchive::new(Cursor::new(bytes)) {
+                Ok(archive) if archive.index_for_name("word/document.xml").is_some() => Some(Format::Docx),
+                _ => Some(Format::LinkedIn),
+            },
+            _ if bytes.trim_ascii_start().starts_with(b"{") => Some(Format::JsonResume),
+            _ if std::str::from_utf8(bytes).is_ok() => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    /// Profiles are mapped as they are; CVs are read by the model.
+    fn is_profile(&self) -> bool {
+        matches!(self, Format::LinkedIn | Format::JsonResume)
+    }
+
+    fn source(&self) -> &'static str {
+        match self {
+            Format::LinkedIn => "LinkedIn profile",
+            Format::JsonResume => "JSON Resume",
+            Format::Pdf | Format::Docx | Format::Text => "CV",
+        }
+    }
+}
+
+fn extract_text(format: Format, bytes: &[u8]) -> Result<String, &'static str> {
+    match format {
+        Format::Pdf => pdf_extract::extract_text_from_mem(bytes).map_err(|e| {
+            warn!("Failed to read PDF: {e}");
+            "the PDF can't be read"
+        }),
+        Format::Docx => docx_text(bytes),
+        Format::Text => String::from_utf8(bytes.to_vec()).map_err(|_| "the text is not UTF-8"),
+        Format::LinkedIn | Format::JsonResume => unreachable!("profiles are not read as text"),
+    }
+}
+
+/// Reads the paragraphs of `word/document.xml`, ignoring styles and everything that isn't text.
+fn docx_text(bytes: &[u8]) -> Result<String, &'static str> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|_| "the DOCX can't be read")?;
+    let mut xml = String::new();
+    archive.by_name("word/document.xml")
+        .map_err(|_| "the DOCX has no document")?
+        .read_to_string(&mut xml)
+        .map_err(|_| "the DOCX can't be read")?;
+
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.local_name().as_ref() == b"t" => in_text = true,
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"t" => in_text = false,
+            Ok(Event::End(e)) if e.local_name().as_ref() == b"p" => text.push('\n'),
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"tab" => text.push('\t'),
+            Ok(Event::Empty(e)) if e.local_name().as_ref() == b"br" => text.push('\n'),
+            Ok(Event::Text(t)) if in_text => text.push_str(&t.unescape().map_err(|_| "the DOCX can't be read")?),
+            Ok(Event::Eof) => break,
+            Err(_) => return Err("the DOCX can't be read"),
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// A file sent as the `file` field of a multipart form, along with the form's text fields.
+pub struct Upload {
+    content_type: Option<String>,
+    file_name: Option<String>,
+    bytes: Bytes,
+    pub fields: HashMap<String, String>,
+}
+
+impl Upload {
+    pub async fn read(mut multipart: Multipart) -> Result<Self, Response> {
+        let mut file = None;
+        let mut fields = HashMap::new();
+        loop {
+            match multipart.next_field().await {
+                Ok(Some(field)) if field.name() == Some("file") => {
+                    let content_type = field.content_type().map(str::to_string);
+                    let file_name = field.file_name().map(str::to_string);
+                    match field.bytes().await {
+                        Ok(bytes) => file = Some((content_type, file_name, bytes)),
+                        Err(e) => return Err(unprocessable(&e.body_text())),
+                    }
+                }
+                Ok(Some(field)) => {
+                    let name = field.name().unwrap_or_default().to_string();
+                    match field.text().await {
+                        Ok(text) => fields.insert(name, text),
+                        Err(e) => return Err(unprocessable(&e.body_text())),
+                    };
+                }
+                Ok(None) => break,
+                Err(e) => return Err(unprocessable(&e.body_text())),
+            }
+        }
+
+        match file {
+            Some((content_type, file_name, bytes)) => Ok(Upload { content_type, file_name, bytes, fields }),
+            None => Err(unprocessable("the file must be sent in the \"file\" field")),
+        }
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+
+    fn format(&self) -> Option<Format> {
+        Format::detect(self.content_type.as_deref(), self.file_name.as_deref(), &self.bytes)
+    }
+
+    /// Text of a PDF, DOCX or plain text document, cut to a length the model can take.
+    pub async fn text(self) -> Result<String, Response> {
+        let format = match self.format() {
+            Some(format) if !format.is_profile() => format,
+            _ => return Err(unprocessable("only PDF, DOCX and plain text documents are supported")),
+        };
+        let bytes = self.bytes;
+        let text = match tokio::task::spawn_blocking(move || extract_text(format, &bytes)).await {
+            Ok(Ok(text)) => text,
+            Ok(Err(e)) => return Err(unprocessable(e)),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        };
+        let text: String = text.trim().chars().take(MAX_TEXT_CHARS).collect();
+        match text.is_empty() {
+            true => Err(unprocessable("no text found in the document")),
+            false => Ok(text),
+        }
+    }
+}
+
+/// Fills in the profession and the survey from a profile without asking the model.
+async fn import_profile(app_state: &AppState, mut user: User, format: Format, bytes: Vec<u8>) -> Result<(usize, Dialogue), Response> {
+    if !matches!(user.get_stage(), Stage::Profession | Stage::Questions) {
+        return Err(conflict("a profile can only be imported before the questions are ready"));
+    }
+    let profile = tokio::task::spawn_blocking(move || match format {
+        Format::LinkedIn => Profile::from_linkedin(&bytes),
+        _ => Profile::from_json_resume(&bytes),
+    }).await;
+    let profile = match profile {
+        Ok(Ok(profile)) => profile,
+        Ok(Err(e)) => return Err(unprocessable(e)),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    };
+
+    let imported = user.import_profile(profile, format.source()).map_err(unprocessable)?;
+    let asker = new_asker(app_state, user.get_tenant(), None);
+    Ok((imported, Dialogue::new(user, asker, None, None)))
+}
+
+/// Lets the model answer what it can from the text of a CV.
+async fn import_cv(app_state: &AppState, user: User, upload: Upload) -> Result<(usize, Dialogue), Response> {
+    if user.get_stage() != Stage::Answers {
+        return Err(conflict("a CV can be imported once the questions are ready"));
+    }
+    let text = upload.text().await?;
+
+    let asker = new_asker(app_state, user.get_tenant(), None);
+    let mut dialogue = Dialogue::new(user, asker, None, None);
+    match dialogue.import_cv(&text).await {
+        Ok(imported) => Ok((imported, dialogue)),
+        Err(e) => {
+            dialogue.save_user(&app_state.pool).await;
+            Err(bad_gateway(&e))
+        }
+    }
+}
+
+/// Takes a CV or a profile as the `file` field of a multipart form, answers what it can from it and asks about the rest.
+pub async fn user_import(Path(id): Path<i32>, State(app_state): State<AppState>, multipart: Multipart) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let upload = match Upload::read(multipart).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let Some(format) = upload.format() else {
+        return unprocessable("only PDF, DOCX and plain text CVs, LinkedIn archives and JSON Resumes are supported");
+    };
+    let imported = match format.is_profile() {
+        true => import_profile(&app_state, user, format, upload.bytes.to_vec()).await,
+        false => import_cv(&app_state, user, upload).await,
+    };
+    let (imported, dialogue) = match imported {
+        Ok(imported) => imported,
+        Err(response) => return response,
+    };
+
+    match dialogue_reply(&app_state, dialogue).await {
+        Ok((generated, message, user)) => Json(json!({
+            "imported": imported,
+            "message": message,
+            "generated": generated,
+            "progress": user.progress(),
+        })).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
@@ -0,0 +1,49 @@
+/// Languages the interview and the CV can be conducted in: ISO 639-1 code, ISO 639-3 code, English name.
+const LANGUAGES: &[(&str, &str, &str)] = &[
+    ("en", "eng", "English"),
+    ("ru", "rus", "Russian"),
+    ("uk", "ukr", "Ukrainian"),
+    ("be", "bel", "Belarusian"),
+    ("kk", "kaz", "Kazakh"),
+    ("de", "deu", "German"),
+    ("fr", "fra", "French"),
+    ("es", "spa", "Spanish"),
+    ("it", "ita", "Italian"),
+    ("pt", "por", "Portuguese"),
+    ("nl", "nld", "Dutch"),
+    ("pl", "pol", "Polish"),
+    ("cs", "ces", "Czech"),
+    ("tr", "tur", "Turkish"),
+    ("ar", "ara", "Arabic"),
+    ("he", "heb", "Hebrew"),
+    ("hi", "hin", "Hindi"),
+    ("zh", "cmn", "Chinese"),
+    ("ja", "jpn", "Japanese"),
+    ("ko", "kor", "Korean"),
+];
+
+/// Accepts ISO 639-1/639-3 codes and IETF tags such as `en-US` (Telegram's `language_code`).
+pub fn normalize(code: &str) -> Option<&'static str> {
+    let code = code.trim().to_lowercase();
+    let primary = code.split(['-', '_']).next().unwrap_or_default();
+
+    LANGUAGES.iter()
+        .find(|(code1, code3, _)| *code1 == primary || *code3 == primary)
+        .map(|(code1, _, _)| *code1)
+}
+
+/// Guesses the language of a message; `None` until the text is long enough to be reliable.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+
+    match info.is_reliable() {
+        true => normalize(info.lang().code()),
+        false => None,
+    }
+}
+
+pub fn name(code: &str) -> &'static str {
+    LANGUAGES.iter()
+        .find(|(code1, _, _)| *code1 == code)
+        .map_or("English", |(_, _, name)| *name)
+}
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt::{self, Display, Formatter};
+
+const MAX_STEPS: u32 = 8;
+const MAX_TOKENS_PER_MESSAGE: u32 = 20_000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LoopStop {
+    Steps(u32),
+    Tokens(u32),
+    RepeatedToolCalls,
+}
+
+impl LoopStop {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            LoopStop::Steps(_) => "steps",
+            LoopStop::Tokens(_) => "tokens",
+            LoopStop::RepeatedToolCalls => "repeated_tool_calls",
+        }
+    }
+}
+
+impl Display for LoopStop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopStop::Steps(steps) => write!(f, "no reply after {steps} steps"),
+            LoopStop::Tokens(tokens) => write!(f, "{tokens} tokens spent on one message"),
+            LoopStop::RepeatedToolCalls => write!(f, "the model repeats the same tool calls"),
+        }
+    }
+}
+
+/// Bounds the re-prompt loop that runs while the model answers with tool calls only.
+/// Limits come from `LOOP_MAX_STEPS` and `LOOP_MAX_TOKENS`.
+pub struct LoopGuard {
+    steps: u32,
+    max_steps: u32,
+    tokens_at_start: u32,
+    max_tokens: u32,
+    seen_tool_calls: HashSet<String>,
+}
+
+impl LoopGuard {
+    pub fn new(tokens_spent: u32) -> Self {
+        let max_steps = env::var("LOOP_MAX_STEPS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(MAX_STEPS);
+        let max_tokens = env::var("LOOP_MAX_TOKENS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(MAX_TOKENS_PER_MESSAGE);
+
+        LoopGuard { steps: 0, max_steps, tokens_at_start: tokens_spent, max_tokens, seen_tool_calls: HashSet::new() }
+    }
+
+    /// Records a step that ended without a reply; `tool_calls` are the calls it made.
+    pub fn step(&mut self, tokens_spent: u32, tool_calls: &[String]) -> Result<(), LoopStop> {
+        self.steps += 1;
+
+        let spent = tokens_spent.saturating_sub(self.tokens_at_start);
+        if spent >= self.max_tokens {
+            return Err(LoopStop::Tokens(spent));
+        }
+
+        let repeated = !tool_calls.is_empty() && tool_calls.iter().all(|c| self.seen_tool_calls.contains(c));
+        if repeated {
+            return Err(LoopStop::RepeatedToolCalls);
+        }
+        self.seen_tool_calls.extend(tool_calls.iter().cloned());
+
+        match self.steps >= self.max_steps {
+            true => Err(LoopStop::Steps(self.steps)),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calls(calls: &[&str]) -> Vec<String> {
+        calls.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn stops_after_the_maximum_steps() {
+        let mut guard = LoopGuard::new(0);
+        for step in 1..MAX_STEPS {
+            assert!(guard.step(0, &calls(&[&format!("save_answer {step}")])).is_ok());
+        }
+        assert!(matches!(guard.step(0, &calls(&["save_answer last"])), Err(LoopStop::Steps(MAX_STEPS))));
+    }
+
+    #[test]
+    fn stops_when_one_message_costs_too_many_tokens() {
+        let mut guard = LoopGuard::new(1_000);
+        assert!(guard.step(1_000 + MAX_TOKENS_PER_MESSAGE - 1, &calls(&["a"])).is_ok());
+        assert!(matches!(guard.step(1_000 + MAX_TOKENS_PER_MESSAGE, &calls(&["b"])), Err(LoopStop::Tokens(MAX_TOKENS_PER_MESSAGE))));
+    }
+
+    #[test]
+    fn stops_when_the_same_tool_calls_repeat() {
+        let mut guard = LoopGuard::new(0);
+        assert!(guard.step(0, &calls(&["a", "b"])).is_ok());
+        // A step with a new call among old ones is progress.
+        assert!(guard.step(0, &calls(&["a", "c"])).is_ok());
+        assert!(matches!(guard.step(0, &calls(&["b", "c"])), Err(LoopStop::RepeatedToolCalls)));
+    }
+
+    #[test]
+    fn steps_without_tool_calls_are_not_repeats() {
+        let mut guard = LoopGuard::new(0);
+        assert!(guard.step(0, &[]).is_ok());
+        assert!(guard.step(0, &[]).is_ok());
+    }
+}
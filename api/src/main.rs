@@ -6,6 +6,28 @@ mod dialogue;
 mod message;
 mod pdf;
 mod storage;
+mod tokens;
+mod prompts;
+mod tools;
+mod admin;
+mod i18n;
+mod language;
+mod retry;
+mod loop_guard;
+mod metrics;
+mod stage;
+mod commands;
+mod questions;
+mod form;
+mod entry;
+mod import;
+mod profile;
+mod vacancy;
+mod cover_letter;
+mod ats;
+mod photo;
+mod reply;
+mod status;
 
 
 use std::{env};
@@ -14,10 +36,10 @@ use async_openai::error::OpenAIError;
 use aws_sdk_s3::Client;
 use axum::error_handling::HandleErrorLayer;
 use axum::{BoxError, Json, Router};
-use axum::extract::{Path, State};
+use axum::extract::{DefaultBodyLimit, Path, State};
 use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post};
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
@@ -25,17 +47,24 @@ use tempfile::NamedTempFile;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-use tracing::{info};
+use tracing::{info, warn};
 use uuid::Uuid;
 use crate::ask::Asker;
 use crate::db::create_pool;
 use crate::dialogue::{Dialogue, Instruction};
-use crate::storage::{create_client, delete, load, save};
+use crate::loop_guard::LoopGuard;
+use crate::prompts::{PromptRegistry, DEFAULT_TENANT};
+use crate::stage::Stage;
+use crate::status::{Step, StatusRegistry};
+use crate::storage::{create_client, load, save};
+use crate::user::Mode;
 
 
 enum Answer {
     Message(String),
     Generated,
+    /// Questions are ready to be answered through the form.
+    Form,
 }
 
 fn get_bucket_name() -> String {
@@ -43,71 +72,140 @@ fn get_bucket_name() -> String {
 }
 
 
-async fn get_answer(app_state: AppState, user: user::User, message: UserMessage) -> Result<Answer, &'static str> {
+fn new_asker(app_state: &AppState, tenant: String, open_ai: Option<OpenAI>) -> Asker {
     let default_api_key = env::var("OPENAI_API_KEY").expect("foo");
-    let bucket_name = get_bucket_name();
 
     let default_max_tokens = Some(1000);
-    let asker = match message.open_ai {
+    match open_ai {
         Some(open_ai) => Asker::new(
             open_ai.api_key.unwrap_or(default_api_key),
             open_ai.max_tokens.or(default_max_tokens),
             open_ai.model,
             None,
+            app_state.prompts.clone(),
+            tenant,
         ),
         None => Asker::new(
             default_api_key,
             default_max_tokens,
             None,
             None,
+            app_state.prompts.clone(),
+            tenant,
         )
-    };
+    }
+}
 
+async fn get_answer(app_state: AppState, user: user::User, message: UserMessage) -> Result<Answer, &'static str> {
+    let asker = new_asker(&app_state, user.get_tenant(), message.open_ai);
     let mut dialogue = Dialogue::new(user, asker, message.max_history, message.max_tokens);
 
     let text = message.text.trim();
 
-    if text.len() > dialogue.get_max_message_length() {
-        return Ok(Answer::Message("Invalid message (to long)".to_string()))
+    dialogue.detect_language(message.language_code.as_deref(), text);
+
+    if dialogue.is_too_long(text) {
+        return Ok(Answer::Message(dialogue.text("message_too_long")))
     }
 
-    let (mut response, mut instruction) = dialogue.process_message(Some(text)).await;
+    run_dialogue(&app_state, dialogue, Some(text)).await
+}
+
+/// Renders the CV to PDF, with the photo if there is one, and stores it in the bucket; returns its name there.
+async fn store_resume(app_state: &AppState, html: &str, photo: Option<&str>) -> String {
+    let html = match photo {
+        Some(photo) => {
+            let jpeg = load(&app_state.s3_client, &get_bucket_name(), photo).await.expect("Failed s3_load");
+            photo::embed(html, &jpeg)
+        }
+        None => html.to_string(),
+    };
+    let resume_temp = NamedTempFile::new().unwrap();
+    pdf::generate_pdf(&html, &resume_temp).await.expect("Failed generate pdf");
+
+    let resume_temp_filepath = resume_temp.path().to_str().unwrap().to_string();
+    let resume_name = format!("{}.pdf", Uuid::new_v4());
+    save(&app_state.s3_client, &get_bucket_name(), &resume_temp_filepath, &resume_name).await.expect("failed save_s3");
+    resume_name
+}
+
+/// The model writes the CV in the resume stage and answers messages in the others.
+fn dialogue_step(dialogue: &Dialogue) -> Step {
+    match dialogue.stage() {
+        Stage::Resume => Step::WritingCv,
+        _ => Step::Thinking,
+    }
+}
+
+/// Processes the user's text, or just continues the dialogue when there's none,
+/// until the model replies; renders and stores the CV once it's generated.
+/// Each step is reported through `GET /users/:id/status` meanwhile.
+async fn run_dialogue(app_state: &AppState, mut dialogue: Dialogue, text: Option<&str>) -> Result<Answer, &'static str> {
+    let status = app_state.status.start(dialogue.user_id() as i32);
+
+    status.set(dialogue_step(&dialogue));
+
+    let mut guard = LoopGuard::new(dialogue.tokens_spent());
+    let (mut response, mut instruction) = dialogue.process_message(text).await;
 
     while response.is_none() {
+        if let Err(stop) = guard.step(dialogue.tokens_spent(), dialogue.last_tool_calls()) {
+            warn!("stopped answering user {}: {stop}", dialogue.user_id());
+            metrics::increment("loop_guard_stops_total", &[("reason", stop.reason())]);
+            response = Some(dialogue.text("loop_stuck"));
+            break;
+        }
+
+        status.set(dialogue_step(&dialogue));
         (response, instruction) = dialogue.process_message(match response {
-            Some(ref t) => Some(&t),
+            Some(ref t) => Some(t),
             _ => None
         }).await;
     }
 
-    match instruction {
-        Instruction::SaveResume => {
-            let resume_temp = NamedTempFile::new().unwrap();
-            pdf::generate_pdf(&response.clone().unwrap(), &resume_temp).await.expect("Failed generate pdf");
-
-            let resume_temp_filepath = resume_temp.path().to_str().unwrap().to_string();
-            let resume_name = format!("{}.pdf", Uuid::new_v4().to_string());
-            save(&app_state.s3_client, &bucket_name, &resume_temp_filepath, &resume_name).await.expect("failed save_s3");
-            dialogue.set_resume(&resume_name).await.expect("Failed set resume for user");
-            dialogue.save_user(&app_state.pool).await;
-            return Ok(Answer::Generated)
-        }
-        Instruction::DeleteResume(name) => {
-            delete(&app_state.s3_client, &bucket_name, &name).await.expect("failed delete_s3");
-        }
-        Instruction::None => { }
+    if let Instruction::SaveResume(prompt_version) = instruction {
+        let html = response.unwrap();
+        status.set(Step::RenderingPdf);
+        let resume_name = store_resume(app_state, &html, dialogue.photo().as_deref()).await;
+        let old_resume = dialogue.set_resume(&resume_name, &html, &prompt_version).await.expect("Failed set resume for user");
+        dialogue.save_user(&app_state.pool).await;
+        storage::delete_unused(&app_state.s3_client, &get_bucket_name(), old_resume).await;
+        return Ok(Answer::Generated)
     }
 
     dialogue.save_user(&app_state.pool).await;
 
+    if let Instruction::ShowForm = instruction {
+        return Ok(Answer::Form)
+    }
+
     Ok(Answer::Message(response.unwrap()))
 }
 
+/// Runs the dialogue without a message from the user, after a command, a form or an import;
+/// returns whether a new CV is ready, the reply, and the user as saved by the dialogue.
+async fn dialogue_reply(app_state: &AppState, dialogue: Dialogue) -> Result<(bool, String, user::User), StatusCode> {
+    let id = dialogue.user_id() as i32;
+    let (generated, message) = match run_dialogue(app_state, dialogue, None).await {
+        Ok(Answer::Generated) => (true, "generated".to_string()),
+        Ok(Answer::Message(message)) => (false, message),
+        Ok(Answer::Form) => (false, "form".to_string()),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+
+    match user::User::get_user(&app_state.pool, id).await {
+        Ok(Some(user)) => Ok((generated, message, user)),
+        _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
 #[derive(Derivative, Debug)]
 #[derivative(Clone)]
 struct AppState {
     pool: Pool<Postgres>,
     s3_client: Client,
+    prompts: PromptRegistry,
+    status: StatusRegistry,
 }
 
 #[tokio::main]
@@ -127,13 +225,45 @@ async fn main() -> Result<(), OpenAIError> {
         .run(&pool)
         .await.expect("failed migrations");
 
-    let app_state = AppState { pool, s3_client };
+    let prompts = PromptRegistry::new();
+    prompts.reload(&pool).await.expect("Failed load prompts");
+    prompts.spawn_reloader(pool.clone());
+
+    let app_state = AppState { pool, s3_client, prompts, status: StatusRegistry::default() };
 
     let app = Router::new()
         .route("/users", post(user_create))
         .route("/users/:id", get(user_get))
         .route("/users/:id/message", post(user_message))
         .route("/users/:id/cv", get(user_cv))
+        .route("/users/:id/language", get(user_language_get).put(user_language))
+        .route("/users/:id/commands", post(commands::user_command))
+        .route(
+            "/users/:id/questions",
+            get(questions::questions_get).put(questions::questions_put).patch(questions::questions_patch),
+        )
+        .route(
+            "/users/:id/import",
+            post(import::user_import).layer(DefaultBodyLimit::max(import::MAX_UPLOAD_BYTES)),
+        )
+        .route("/users/:id/vacancies", get(vacancy::vacancies_get).post(vacancy::vacancy_create))
+        .route(
+            "/users/:id/vacancies/upload",
+            post(vacancy::vacancy_upload).layer(DefaultBodyLimit::max(import::MAX_UPLOAD_BYTES)),
+        )
+        .route("/users/:id/vacancies/:vacancy_id", delete(vacancy::vacancy_delete))
+        .route("/users/:id/vacancies/:vacancy_id/cv", get(vacancy::vacancy_cv_get).post(vacancy::vacancy_cv_post))
+        .route("/users/:id/cover-letter", get(cover_letter::cover_letter_get).post(cover_letter::cover_letter_post))
+        .route("/users/:id/ats", post(ats::ats_post))
+        .route(
+            "/users/:id/photo",
+            get(photo::photo_get).put(photo::photo_put).delete(photo::photo_delete)
+                .layer(DefaultBodyLimit::max(import::MAX_UPLOAD_BYTES)),
+        )
+        .route("/users/:id/status", get(status::status_get))
+        .route("/users/:id/form", get(form::form_get).post(form::form_post))
+        .route("/metrics", get(metrics_get))
+        .nest("/admin", admin::router())
         .layer(
             ServiceBuilder::new()
                 .layer(HandleErrorLayer::new(|error: BoxError| async move {
@@ -164,8 +294,23 @@ async fn main() -> Result<(), OpenAIError> {
     Ok(())
 }
 
-async fn user_create(State(app_state): State<AppState>) -> impl IntoResponse {
-    let u = user::User::create_user(&app_state.pool).await.expect("todo");
+async fn metrics_get() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics::render())
+}
+
+#[derive(Debug, Deserialize)]
+struct NewUser {
+    tenant: Option<String>,
+    #[serde(default)]
+    mode: Mode,
+}
+
+async fn user_create(State(app_state): State<AppState>, new_user: Option<Json<NewUser>>) -> impl IntoResponse {
+    let (tenant, mode) = match new_user {
+        Some(Json(u)) => (u.tenant.unwrap_or(DEFAULT_TENANT.to_string()), u.mode),
+        None => (DEFAULT_TENANT.to_string(), Mode::default()),
+    };
+    let u = user::User::create_user(&app_state.pool, &tenant, mode).await.expect("todo");
 
     let user = User { id: u.id };
 
@@ -199,6 +344,8 @@ struct UserMessage {
     open_ai: Option<OpenAI>,
     max_history: Option<usize>,
     max_tokens: Option<u32>,
+    /// Client's UI language (e.g. Telegram `language_code`), used until the user's language is known.
+    language_code: Option<String>,
 }
 
 async fn user_message(Path(id): Path<i32>, State(app_state): State<AppState>, Json(message): Json<UserMessage>) -> impl IntoResponse {
@@ -206,6 +353,7 @@ async fn user_message(Path(id): Path<i32>, State(app_state): State<AppState>, Js
         return match get_answer(app_state, user, message).await {
             Ok(Answer::Message(text)) => Ok(Json(text)),
             Ok(Answer::Generated) => Ok(Json("generated".to_string())),
+            Ok(Answer::Form) => Ok(Json("form".to_string())),
             _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
         }
     }
@@ -230,4 +378,38 @@ async fn user_cv(Path(id): Path<i32>, State(app_state): State<AppState>) -> impl
         }
     }
     Err(StatusCode::NOT_FOUND)
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct UserLanguage {
+    language: Option<String>,
+    cv_language: Option<String>,
+}
+
+/// The languages the user picked or that were detected, so clients can reply in them too.
+async fn user_language_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> impl IntoResponse {
+    let Ok(Some(user)) = user::User::get_user(&app_state.pool, id).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    Ok(Json(UserLanguage { language: user.get_language(), cv_language: user.get_cv_language() }))
+}
+
+async fn user_language(Path(id): Path<i32>, State(app_state): State<AppState>, Json(body): Json<UserLanguage>) -> impl IntoResponse {
+    let Ok(Some(mut user)) = user::User::get_user(&app_state.pool, id).await else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let normalize = |code: Option<String>| match code {
+        Some(code) => language::normalize(&code).map(Some).ok_or(StatusCode::UNPROCESSABLE_ENTITY),
+        None => Ok(None),
+    };
+    if let Some(language) = normalize(body.language)? {
+        user.set_language(language);
+    }
+    if let Some(cv_language) = normalize(body.cv_language)? {
+        user.set_cv_language(cv_language);
+    }
+
+    user.save(&app_state.pool).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(user))
+}
@@ -25,23 +25,23 @@ impl Serialize for Message {
         match &self.0 {
             ChatCompletionRequestMessage::System(msg) => {
                 state.serialize_field("type", "system")?;
-                state.serialize_field("content", &serde_json::to_value(&msg).map_err(|_| "Serialization error").unwrap())?;
+                state.serialize_field("content", &serde_json::to_value(msg).map_err(|_| "Serialization error").unwrap())?;
             }
             ChatCompletionRequestMessage::User(msg) => {
                 state.serialize_field("type", "user")?;
-                state.serialize_field("content", &serde_json::to_value(&msg).map_err(|_| "Serialization error").unwrap())?;
+                state.serialize_field("content", &serde_json::to_value(msg).map_err(|_| "Serialization error").unwrap())?;
             }
             ChatCompletionRequestMessage::Assistant(msg) => {
                 state.serialize_field("type", "assistant")?;
-                state.serialize_field("content", &serde_json::to_value(&msg).map_err(|_| "Serialization error").unwrap())?;
+                state.serialize_field("content", &serde_json::to_value(msg).map_err(|_| "Serialization error").unwrap())?;
             }
             ChatCompletionRequestMessage::Tool(msg) => {
                 state.serialize_field("type", "tool")?;
-                state.serialize_field("content", &serde_json::to_value(&msg).map_err(|_| "Serialization error").unwrap())?;
+                state.serialize_field("content", &serde_json::to_value(msg).map_err(|_| "Serialization error").unwrap())?;
             }
             ChatCompletionRequestMessage::Function(msg) => {
                 state.serialize_field("type", "function")?;
-                state.serialize_field("content", &serde_json::to_value(&msg).map_err(|_| "Serialization error").unwrap())?;
+                state.serialize_field("content", &serde_json::to_value(msg).map_err(|_| "Serialization error").unwrap())?;
             }
         }
 
@@ -0,0 +1,31 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+fn counters() -> &'static Mutex<BTreeMap<String, u64>> {
+    static COUNTERS: OnceLock<Mutex<BTreeMap<String, u64>>> = OnceLock::new();
+    COUNTERS.get_or_init(Default::default)
+}
+
+/// Increments a counter, e.g. `increment("loop_guard_stops_total", &[("reason", "steps")])`.
+pub fn increment(name: &str, labels: &[(&str, &str)]) {
+    let key = match labels.is_empty() {
+        true => name.to_string(),
+        false => {
+            let labels = labels.iter()
+                .map(|(k, v)| format!("{k}=\"{v}\""))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{name}{{{labels}}}")
+        }
+    };
+
+    *counters().lock().expect("metrics lock poisoned").entry(key).or_default() += 1;
+}
+
+/// All counters in the Prometheus text format.
+pub fn render() -> String {
+    counters().lock().expect("metrics lock poisoned")
+        .iter()
+        .map(|(key, value)| format!("{key} {value}\n"))
+        .collect()
+}
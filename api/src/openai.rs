@@ -1,6 +1,8 @@
 use std::env;
-use async_openai::Client;
-use async_openai::config::OpenAIConfig;
+use std::fmt::{self, Display, Formatter};
+use std::ops::AddAssign;
+use std::sync::OnceLock;
+use std::time::Duration;
 use async_openai::error::OpenAIError;
 use async_openai::types::{
     ChatCompletionRequestMessage,
@@ -8,12 +10,21 @@ use async_openai::types::{
     ChatCompletionTool,
     ChatCompletionToolArgs,
     CreateChatCompletionRequestArgs,
+    CreateChatCompletionResponse,
     FunctionObjectArgs,
 };
+use chrono::{DateTime, Utc};
 use derivative::Derivative;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
 use serde_json::{Value};
+use tokio::time::sleep;
+use tracing::warn;
+use crate::retry::{CircuitBreaker, RetryPolicy};
 
-
+pub const DEFAULT_MAX_TOKENS: u16 = 512;
+const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+const REQUEST_TIMEOUT_SECS: u64 = 120;
 
 #[derive(Derivative)]
 #[derivative(Debug, Default)]
@@ -53,13 +64,11 @@ impl Request {
             false => None
         };
 
-        let model = model.unwrap_or_else(
-            || env::var("DEFAULT_MODEL").unwrap_or("gpt-3.5-turbo".to_string())
-        );
+        let model = resolve_model(model);
 
         Request {
             api_key,
-            max_tokens: max_tokens.unwrap_or(512),
+            max_tokens: max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
             model,
             messages,
             tool_calls,
@@ -67,37 +76,202 @@ impl Request {
     }
 }
 
+pub fn resolve_model(model: Option<String>) -> String {
+    model.unwrap_or_else(
+        || env::var("DEFAULT_MODEL").unwrap_or("gpt-3.5-turbo".to_string())
+    )
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self { prompt_tokens, completion_tokens }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
 pub struct ChatResponse {
     pub message: ChatCompletionResponseMessage,
-    pub tokens_spent: u32,
+    pub usage: Option<TokenUsage>,
 }
 
-pub async fn get_response(request: Request) -> Result<ChatResponse, OpenAIError> {
-    let config = OpenAIConfig::new().with_api_key(&request.api_key);
-    let client = Client::with_config(config);
+#[derive(Debug)]
+pub enum LlmError {
+    Request(OpenAIError),
+    Http(reqwest::Error),
+    Status { status: StatusCode, retry_after: Option<Duration>, body: String },
+    EmptyResponse,
+    CircuitOpen(String),
+}
+
+impl LlmError {
+    /// Rate limits, server errors and network failures are worth another attempt.
+    fn is_transient(&self) -> bool {
+        match self {
+            LlmError::Http(_) => true,
+            LlmError::Status { status, .. } => status.as_u16() == 429 || status.as_u16() == 408 || status.is_server_error(),
+            LlmError::CircuitOpen(_) => true,
+            LlmError::Request(_) | LlmError::EmptyResponse => false,
+        }
+    }
 
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            LlmError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl Display for LlmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Request(e) => write!(f, "invalid request: {e}"),
+            LlmError::Http(e) => write!(f, "http error: {e}"),
+            LlmError::Status { status, body, .. } => write!(f, "{status}: {body}"),
+            LlmError::EmptyResponse => write!(f, "response has no choices"),
+            LlmError::CircuitOpen(model) => write!(f, "circuit breaker for {model} is open"),
+        }
+    }
+}
+
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        let timeout = env::var("LLM_TIMEOUT_SECS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(REQUEST_TIMEOUT_SECS);
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout))
+            .build()
+            .expect("Failed to build http client")
+    })
+}
+
+/// Sends the request, retrying transient failures with backoff and falling back
+/// to `LLM_FALLBACK_MODELS` in order once a model keeps failing or its circuit is open.
+pub async fn get_response(request: Request) -> Result<ChatResponse, LlmError> {
+    let policy = RetryPolicy::from_env();
+    let mut last_error = LlmError::CircuitOpen(request.model.clone());
+
+    for model in policy.models(&request.model) {
+        if !CircuitBreaker::allows(&model) {
+            warn!("skipping {model}: circuit breaker is open");
+            last_error = LlmError::CircuitOpen(model);
+            continue;
+        }
+
+        match get_response_with_retries(&request, &model, &policy).await {
+            Ok(response) => {
+                if model != request.model {
+                    warn!("answered by fallback model {model} instead of {}", request.model);
+                }
+                return Ok(response);
+            }
+            Err(e) if e.is_transient() => {
+                warn!("{model} failed, trying the next model: {e}");
+                last_error = e;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(last_error)
+}
+
+async fn get_response_with_retries(request: &Request, model: &str, policy: &RetryPolicy) -> Result<ChatResponse, LlmError> {
+    let mut attempt = 0;
+    loop {
+        let error = match send(request, model).await {
+            Ok(response) => {
+                CircuitBreaker::success(model);
+                return Ok(response);
+            }
+            Err(e) if e.is_transient() => e,
+            Err(e) => {
+                CircuitBreaker::success(model);
+                return Err(e);
+            }
+        };
+
+        let open = CircuitBreaker::failure(model);
+        let delay = policy.delay(attempt, error.retry_after());
+        if open || attempt >= policy.max_retries {
+            return Err(error);
+        }
+
+        attempt += 1;
+        warn!("{model} attempt {attempt}/{} failed, retrying in {delay:?}: {error}", policy.max_retries);
+        sleep(delay).await;
+    }
+}
+
+async fn send(request: &Request, model: &str) -> Result<ChatResponse, LlmError> {
     let mut args = CreateChatCompletionRequestArgs::default();
     let request_builder = args
         .max_tokens(request.max_tokens)
-        .model(request.model)
-        .messages(request.messages);
+        .model(model)
+        .messages(request.messages.clone());
 
-    if let Some(tool_calls) = request.tool_calls {
-        request_builder.tools(tool_calls);
+    if let Some(tool_calls) = &request.tool_calls {
+        request_builder.tools(tool_calls.clone());
     };
-    let request = request_builder.build()?;
+    let body = request_builder.build().map_err(LlmError::Request)?;
 
-    let response = client.chat()
-        .create(request)
-        .await?;
+    let api_base = env::var("OPENAI_API_BASE").unwrap_or(DEFAULT_API_BASE.to_string());
+    let response = http_client()
+        .post(format!("{api_base}/chat/completions"))
+        .bearer_auth(&request.api_key)
+        .json(&body)
+        .send()
+        .await
+        .map_err(LlmError::Http)?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let retry_after = retry_after(response.headers());
+        let body = response.text().await.unwrap_or_default();
+        return Err(LlmError::Status { status, retry_after, body });
+    }
+
+    let response: CreateChatCompletionResponse = response.json().await.map_err(LlmError::Http)?;
+    let choice = response.choices.into_iter().next().ok_or(LlmError::EmptyResponse)?;
 
     Ok(
         ChatResponse {
-            message: response.choices.get(0).unwrap().message.clone(),
-            tokens_spent: match response.usage {
-                Some(u) => u.total_tokens,
-                _ => 0
-            },
+            message: choice.message,
+            usage: response.usage.map(|u| TokenUsage::new(u.prompt_tokens, u.completion_tokens)),
         }
     )
-}
\ No newline at end of file
+}
+
+/// Reads `retry-after-ms`, then `Retry-After` as seconds or an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(Duration::from_millis(ms.max(0.0) as u64));
+    }
+
+    let value = header("retry-after")?;
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => DateTime::parse_from_rfc2822(value).ok()
+            .and_then(|date| (date.with_timezone(&Utc) - Utc::now()).to_std().ok()),
+    }
+}
@@ -18,7 +18,7 @@ pub async fn generate_pdf(text: &str, output_temp: &NamedTempFile) -> Result<(),
     let output_temp_filepath = output_temp.path().to_str().unwrap().to_string();
 
     let mut command = Command::new(program);
-    command.args(&[&input_temp_filepath, &output_temp_filepath]);
+    command.args([&input_temp_filepath, &output_temp_filepath]);
 
     let child = command.spawn()?;
     let _ = child.wait_with_output().await?;
@@ -0,0 +1,185 @@
+use std::io::{Cursor, Write};
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageDecoder, ImageReader, Limits, RgbImage};
+use serde_json::json;
+use tempfile::NamedTempFile;
+use tracing::warn;
+use uuid::Uuid;
+use crate::import::Upload;
+use crate::reply::unprocessable;
+use crate::storage::{delete_unused, load, save};
+use crate::user::User;
+use crate::{get_bucket_name, AppState};
+
+/// Photos are scaled down to fit this square; a CV shows them much smaller, but they stay sharp in print.
+const MAX_SIDE: u32 = 600;
+const MIN_SIDE: u32 = 100;
+/// Larger images are rejected before they are decoded.
+const MAX_DECODED_SIDE: u32 = 10_000;
+const JPEG_QUALITY: u8 = 85;
+
+/// Reads a JPEG, PNG or WebP image, turns it upright and scales it down, and stores it as a JPEG,
+/// leaving out metadata such as the location it was taken at. Transparency becomes white.
+pub fn normalise(bytes: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODED_SIDE);
+    limits.max_image_height = Some(MAX_DECODED_SIDE);
+
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().map_err(|_| "the image can't be read")?;
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(|_| "only JPEG, PNG and WebP images are supported")?;
+    let orientation = decoder.orientation().map_err(|_| "the image can't be read")?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(|e| {
+        warn!("Failed to decode photo: {e}");
+        "the image can't be read"
+    })?;
+    image.apply_orientation(orientation);
+
+    if image.width() < MIN_SIDE || image.height() < MIN_SIDE {
+        return Err("the photo is too small, it needs to be at least 100 pixels on each side");
+    }
+    if image.width() > MAX_SIDE || image.height() > MAX_SIDE {
+        image = image.resize(MAX_SIDE, MAX_SIDE, image::imageops::FilterType::Lanczos3);
+    }
+
+    let rgba = image.to_rgba8();
+    let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let over_white = |c: u8| ((c as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+        image::Rgb([over_white(r), over_white(g), over_white(b)])
+    });
+
+    let mut jpeg = vec![];
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode_image(&rgb)
+        .map_err(|_| "the image can't be converted")?;
+    Ok(jpeg)
+}
+
+/// Puts the photo at the top right of the CV, right after `<body>` or, without one, before everything else.
+pub fn embed(html: &str, jpeg: &[u8]) -> String {
+    let img = format!(
+        "<img src=\"data:image/jpeg;base64,{}\" alt=\"\" style=\"float: right; width: 110px; margin: 0 0 12px 16px; border-radius: 4px;\">",
+        STANDARD.encode(jpeg),
+    );
+    let position = html.to_ascii_lowercase()
+        .find("<body")
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+    format!("{}{img}{}", &html[..position], &html[position..])
+}
+
+/// Takes the photo as the `file` field of a multipart form; CVs made from now on show it.
+pub async fn photo_put(Path(id): Path<i32>, State(app_state): State<AppState>, multipart: Multipart) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let upload = match Upload::read(multipart).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+
+    let bytes = upload.into_bytes();
+    let jpeg = match tokio::task::spawn_blocking(move || normalise(&bytes)).await {
+        Ok(Ok(jpeg)) => jpeg,
+        Ok(Err(e)) => return unprocessable(e),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let mut photo_temp = NamedTempFile::new().unwrap();
+    photo_temp.write_all(&jpeg).expect("Failed write photo");
+    let photo_name = format!("{}.jpg", Uuid::new_v4());
+    save(&app_state.s3_client, &get_bucket_name(), photo_temp.path().to_str().unwrap(), &photo_name).await.expect("failed save_s3");
+
+    let old_photo = user.set_photo(Some(&photo_name));
+    if user.save(&app_state.pool).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    delete_photo(&app_state, old_photo).await;
+    Json(json!({ "saved": true })).into_response()
+}
+
+pub async fn photo_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(name) = user.get_photo() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let bytes = load(&app_state.s3_client, &get_bucket_name(), &name).await.expect("Failed s3_load");
+    ([(header::CONTENT_TYPE, "image/jpeg")], bytes).into_response()
+}
+
+/// CVs made from now on leave the photo out.
+pub async fn photo_delete(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let old_photo = user.set_photo(None);
+    if user.save(&app_state.pool).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    delete_photo(&app_state, old_photo).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn delete_photo(app_state: &AppState, name: Option<String>) {
+    delete_unused(&app_state.s3_client, &get_bucket_name(), name).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, RgbaImage};
+    use super::*;
+
+    fn png(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+        let mut bytes = Cursor::new(vec![]);
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, image::Rgba(pixel)))
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn photo_goes_right_after_the_body_tag() {
+        let html = embed("<html><BODY class=\"cv\"><h1>Jane</h1></BODY></html>", b"jpeg");
+        let img = format!("<img src=\"data:image/jpeg;base64,{}\"", STANDARD.encode(b"jpeg"));
+        assert!(html.starts_with(&format!("<html><BODY class=\"cv\">{img}")), "{html}");
+        assert!(html.ends_with("<h1>Jane</h1></BODY></html>"));
+    }
+
+    #[test]
+    fn photo_goes_first_without_a_body() {
+        let html = embed("<h1>Jane</h1>", b"jpeg");
+        assert!(html.starts_with("<img "));
+        assert!(html.ends_with("<h1>Jane</h1>"));
+    }
+
+    #[test]
+    fn large_photo_is_scaled_down_to_a_jpeg() {
+        let jpeg = normalise(&png(1200, 800, [200, 100, 50, 255])).unwrap();
+        let image = image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg).unwrap();
+        assert_eq!((image.width(), image.height()), (MAX_SIDE, 400));
+    }
+
+    #[test]
+    fn transparency_becomes_white() {
+        let jpeg = normalise(&png(120, 120, [0, 0, 0, 0])).unwrap();
+        let image = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert!(image.get_pixel(60, 60).0.iter().all(|c| *c > 250));
+    }
+
+    #[test]
+    fn small_or_unreadable_photos_are_rejected() {
+        assert!(normalise(&png(50, 200, [0, 0, 0, 255])).unwrap_err().contains("too small"));
+        assert!(normalise(b"GIF89a not really").is_err());
+        assert!(normalise(b"plain text").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use serde::Deserialize;
+use crate::entry::{self, Entry, Job, Study};
+use crate::user::{AnswerType, QuestionInput, Section};
+
+/// What is already known about the user from a LinkedIn export or a JSON Resume.
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub profession: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    location: Option<String>,
+    links: Vec<String>,
+    summary: Option<String>,
+    jobs: Vec<Job>,
+    studies: Vec<Study>,
+    skills: Vec<String>,
+    languages: Vec<String>,
+    certifications: Vec<String>,
+}
+
+impl Profile {
+    /// Reads the CSV files of a LinkedIn "Download your data" archive; files that are missing are left out.
+    pub fn from_linkedin(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|_| "the archive can't be read")?;
+        let mut csv_file = |name: &str| -> Vec<HashMap<String, String>> {
+            let Some(path) = archive.file_names().find(|f| f.rsplit('/').next() == Some(name)).map(str::to_string) else {
+                return vec![];
+            };
+            let mut content = String::new();
+            if archive.by_name(&path).and_then(|mut f| f.read_to_string(&mut content).map_err(Into::into)).is_err() {
+                return vec![];
+            }
+            read_csv(&content)
+        };
+
+        let profile = csv_file("Profile.csv").into_iter().next().unwrap_or_default();
+        let positions = csv_file("Positions.csv");
+        let education = csv_file("Education.csv");
+        let skills = csv_file("Skills.csv");
+        let emails = csv_file("Email Addresses.csv");
+        let phones = csv_file("PhoneNumbers.csv");
+        let languages = csv_file("Languages.csv");
+        let certifications = csv_file("Certifications.csv");
+        if profile.is_empty() && positions.is_empty() && education.is_empty() {
+            return Err("no LinkedIn profile found in the archive");
+        }
+
+        let jobs: Vec<Job> = positions.iter()
+            .filter_map(|p| Some(Job {
+                employer: field(p, "Company Name")?,
+                title: field(p, "Title")?,
+                start: field(p, "Started On"),
+                end: field(p, "Finished On"),
+                achievements: field(p, "Description").map(|d| lines(&d)).unwrap_or_default(),
+            }))
+            .collect();
+        let name = [field(&profile, "First Name"), field(&profile, "Last Name")]
+            .into_iter().flatten().collect::<Vec<_>>().join(" ");
+
+        Ok(Profile {
+            profession: field(&profile, "Headline").or_else(|| jobs.first().map(|j| j.title.clone())),
+            name: Some(name).filter(|n| !n.is_empty()),
+            email: emails.iter()
+                .find(|e| field(e, "Primary").as_deref() == Some("Yes"))
+                .or(emails.first())
+                .and_then(|e| field(e, "Email Address")),
+            phone: phones.first().and_then(|p| field(p, "Number")),
+            location: field(&profile, "Geo Location").or_else(|| field(&profile, "Address")),
+            links: field(&profile, "Websites").map(|w| list(&w)).unwrap_or_default(),
+            summary: field(&profile, "Summary"),
+            jobs,
+            studies: education.iter()
+                .filter_map(|e| Some(Study {
+                    institution: field(e, "School Name")?,
+                    degree: field(e, "Degree Name").or_else(|| field(e, "Notes")).unwrap_or_default(),
+                    start: field(e, "Start Date"),
+                    end: field(e, "End Date"),
+                }))
+                .collect(),
+            skills: skills.iter().filter_map(|s| field(s, "Name")).collect(),
+            languages: languages.iter()
+                .filter_map(|l| Some(match field(l, "Proficiency") {
+                    Some(proficiency) => format!("{} ({proficiency})", field(l, "Name")?),
+                    None => field(l, "Name")?,
+                }))
+                .collect(),
+            certifications: certifications.iter().filter_map(|c| field(c, "Name")).collect(),
+        })
+    }
+
+    /// Reads a document in the JSON Resume schema (https://jsonresume.org/schema).
+    pub fn from_json_resume(bytes: &[u8]) -> Result<Self, &'static str> {
+        let resume: JsonResume = serde_json::from_slice(bytes).map_err(|_| "the JSON is not a JSON Resume")?;
+        if resume.basics.name.is_none() && resume.work.is_empty() && resume.education.is_empty() {
+            return Err("no resume found in the JSON");
+        }
+        let basics = resume.basics;
+
+        let location = basics.location
+            .map(|l| [l.city, l.region, l.country_code].into_iter().flatten().filter(|p| !p.is_empty()).collect::<Vec<_>>().join(", "))
+            .filter(|l| !l.is_empty());
+        let links = basics.url.into_iter()
+            .chain(basics.profiles.into_iter().filter_map(|p| p.url))
+            .filter(|l| !l.is_empty())
+            .collect();
+
+        let jobs: Vec<Job> = resume.work.into_iter()
+            .filter_map(|w| Some(Job {
+                employer: w.name?,
+                title: w.position?,
+                start: w.start_date,
+                end: w.end_date,
+                achievements: match w.highlights.is_empty() {
+                    true => w.summary.map(|s| lines(&s)).unwrap_or_default(),
+                    false => w.highlights,
+                },
+            }))
+            .collect();
+
+        Ok(Profile {
+            profession: basics.label.filter(|l| !l.is_empty()).or_else(|| jobs.first().map(|j| j.title.clone())),
+            name: basics.name,
+            email: basics.email,
+            phone: basics.phone,
+            location,
+            links,
+            summary: basics.summary,
+            jobs,
+            studies: resume.education.into_iter()
+                .filter_map(|e| Some(Study {
+                    institution: e.institution?,
+                    degree: [e.study_type, e.area].into_iter().flatten().collect::<Vec<_>>().join(", "),
+                    start: e.start_date,
+                    end: e.end_date,
+                }))
+                .collect(),
+            skills: resume.skills.into_iter()
+                .flat_map(|s| s.name.into_iter().chain(s.keywords))
+                .collect(),
+            languages: resume.languages.into_iter()
+                .filter_map(|l| Some(match l.fluency {
+                    Some(fluency) => format!("{} ({fluency})", l.language?),
+                    None => l.language?,
+                }))
+                .collect(),
+            certifications: resume.certificates.into_iter().filter_map(|c| c.name).collect(),
+        })
+    }
+
+    /// The survey with everything known already answered; the rest is left for the interview.
+    pub fn questions(self) -> Vec<QuestionInput> {
+        let entries = |entries: Vec<Entry>| (entry::answer(&entries), entries);
+        let (jobs_answer, jobs) = entries(self.jobs.into_iter().map(Entry::Job).collect());
+        let (studies_answer, studies) = entries(self.studies.into_iter().map(Entry::Study).collect());
+
+        vec![
+            question("Full name", Section::Personal, AnswerType::Text, true, self.name),
+            question("Email address", Section::Personal, AnswerType::Text, true, self.email),
+            question("Phone number", Section::Personal, AnswerType::Text, false, self.phone),
+            question("City and country", Section::Personal, AnswerType::Text, false, self.location),
+            question("Links to your profiles and website", Section::Personal, AnswerType::List, false, joined(self.links)),
+            question("Professional summary", Section::Summary, AnswerType::Text, true, self.summary),
+            QuestionInput { entries: jobs, ..question("Work history", Section::Experience, AnswerType::WorkHistory, true, jobs_answer) },
+            QuestionInput { entries: studies, ..question("Education", Section::Education, AnswerType::Education, true, studies_answer) },
+            question("Skills", Section::Skills, AnswerType::List, true, joined(self.skills)),
+            question("Languages you speak and how well", Section::Skills, AnswerType::List, false, joined(self.languages)),
+            question("Certifications and licenses", Section::Achievements, AnswerType::List, false, joined(self.certifications)),
+        ]
+    }
+}
+
+fn question(text: &str, section: Section, answer_type: AnswerType, required: bool, answer: Option<String>) -> QuestionInput {
+    QuestionInput {
+        section,
+        answer_type,
+        required,
+        answer: answer.filter(|a| !a.trim().is_empty()),
+        ..QuestionInput::new(text)
+    }
+}
+
+fn joined(items: Vec<String>) -> Option<String> {
+    Some(items.join("; ")).filter(|i| !i.is_empty())
+}
+
+fn read_csv(content: &str) -> Vec<HashMap<String, String>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let Ok(headers) = reader.headers().cloned() else {
+        return vec![];
+    };
+    reader.records()
+        .flatten()
+        .map(|record| headers.iter().map(str::to_string).zip(record.iter().map(str::to_string)).collect())
+        .collect()
+}
+
+fn field(row: &HashMap<String, String>, name: &str) -> Option<String> {
+    row.get(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// Splits a description into achievements, one per line, without bullet marks.
+fn lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|l| l.trim().trim_start_matches(['•', '-', '*']).trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// LinkedIn lists websites as `[TYPE:url,TYPE:url]`.
+fn list(text: &str) -> Vec<String> {
+    text.trim_matches(['[', ']'])
+        .split(',')
+        .map(|w| w.split_once(':').filter(|(kind, _)| kind.chars().all(|c| c.is_ascii_uppercase())).map_or(w, |(_, url)| url))
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct JsonResume {
+    basics: Basics,
+    work: Vec<Work>,
+    education: Vec<Education>,
+    skills: Vec<Skill>,
+    languages: Vec<Language>,
+    certificates: Vec<Certificate>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Basics {
+    name: Option<String>,
+    label: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    url: Option<String>,
+    summary: Option<String>,
+    location: Option<Location>,
+    profiles: Vec<ProfileLink>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Location {
+    city: Option<String>,
+    region: Option<String>,
+    country_code: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProfileLink {
+    url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Work {
+    name: Option<String>,
+    position: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    summary: Option<String>,
+    highlights: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Education {
+    institution: Option<String>,
+    area: Option<String>,
+    study_type: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Skill {
+    name: Option<String>,
+    keywords: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Language {
+    language: Option<String>,
+    fluency: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Certificate {
+    name: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+
+    fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in files {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn linkedin_archive_is_read() {
+        let archive = zip(&[
+            ("Basic_LinkedInDataExport/Profile.csv",
+                "First Name,Last Name,Headline,Summary,Geo Location,Websites\n\
+                 Jane,Doe,Data Engineer,Builds pipelines.,Berlin,\"[PORTFOLIO:https://jane.dev,OTHER:https://github.com/jane]\"\n"),
+            ("Positions.csv",
+                "Company Name,Title,Description,Started On,Finished On\n\
+                 Acme,Data Engineer,\"• Cut costs by 30%\n- Led a team of 4\",Jan 2020,\n\
+                 ,No company,,,\n"),
+            ("Education.csv", "School Name,Start Date,End Date,Notes,Degree Name\nTU Berlin,2014,2018,,MSc Computer Science\n"),
+            ("Skills.csv", "Name\nRust\nKafka\n"),
+            ("Email Addresses.csv", "Email Address,Confirmed,Primary\nold@example.com,Yes,No\njane@example.com,Yes,Yes\n"),
+            ("Languages.csv", "Name,Proficiency\nGerman,Native or bilingual proficiency\nEnglish,\n"),
+        ]);
+
+        let profile = Profile::from_linkedin(&archive).unwrap();
+        assert_eq!(profile.profession.as_deref(), Some("Data Engineer"));
+        assert_eq!(profile.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(profile.email.as_deref(), Some("jane@example.com"));
+        assert_eq!(profile.location.as_deref(), Some("Berlin"));
+        assert_eq!(profile.links, ["https://jane.dev", "https://github.com/jane"]);
+        assert_eq!(profile.jobs.len(), 1);
+        assert_eq!(profile.jobs[0].achievements, ["Cut costs by 30%", "Led a team of 4"]);
+        assert_eq!(profile.jobs[0].end, None);
+        assert_eq!(profile.studies[0].degree, "MSc Computer Science");
+        assert_eq!(profile.skills, ["Rust", "Kafka"]);
+        assert_eq!(profile.languages, ["German (Native or bilingual proficiency)", "English"]);
+    }
+
+    #[test]
+    fn linkedin_archive_without_a_profile_is_rejected() {
+        assert_eq!(Profile::from_linkedin(&zip(&[("Skills.csv", "Name\nRust\n")])).unwrap_err(), "no LinkedIn profile found in the archive");
+        assert_eq!(Profile::from_linkedin(b"not a zip").unwrap_err(), "the archive can't be read");
+    }
+
+    #[test]
+    fn json_resume_is_read() {
+        let resume = br#"{
+            "basics": {
+                "name": "Jane Doe",
+                "label": "",
+                "email": "jane@example.com",
+                "url": "https://jane.dev",
+                "location": {"city": "Berlin", "countryCode": "DE"},
+                "profiles": [{"network": "GitHub", "url": "https://github.com/jane"}]
+            },
+            "work": [
+                {"name": "Acme", "position": "Data Engineer", "startDate": "2020-01", "summary": "Built pipelines.\nCut costs."},
+                {"name": "Initech", "position": "Developer", "highlights": ["Shipped the billing system"]}
+            ],
+            "education": [{"institution": "TU Berlin", "studyType": "MSc", "area": "Computer Science"}],
+            "skills": [{"name": "Data", "keywords": ["Kafka", "Spark"]}],
+            "languages": [{"language": "German", "fluency": "Native"}]
+        }"#;
+
+        let profile = Profile::from_json_resume(resume).unwrap();
+        // An empty label falls back to the latest job.
+        assert_eq!(profile.profession.as_deref(), Some("Data Engineer"));
+        assert_eq!(profile.location.as_deref(), Some("Berlin, DE"));
+        assert_eq!(profile.links, ["https://jane.dev", "https://github.com/jane"]);
+        assert_eq!(profile.jobs[0].achievements, ["Built pipelines.", "Cut costs."]);
+        assert_eq!(profile.jobs[1].achievements, ["Shipped the billing system"]);
+        assert_eq!(profile.studies[0].degree, "MSc, Computer Science");
+        assert_eq!(profile.skills, ["Data", "Kafka", "Spark"]);
+        assert_eq!(profile.languages, ["German (Native)"]);
+    }
+
+    #[test]
+    fn json_without_a_resume_is_rejected() {
+        assert_eq!(Profile::from_json_resume(b"{\"version\": 1}").unwrap_err(), "no resume found in the JSON");
+        assert_eq!(Profile::from_json_resume(b"[1, 2]").unwrap_err(), "the JSON is not a JSON Resume");
+    }
+
+    #[test]
+    fn questions_leave_what_is_unknown_open() {
+        let profile = Profile {
+            name: Some("Jane Doe".to_string()),
+            skills: vec!["Rust".to_string(), "Kafka".to_string()],
+            summary: Some("  ".to_string()),
+            ..Profile::default()
+        };
+        let questions = profile.questions();
+        let answer = |text: &str| questions.iter().find(|q| q.question == text).unwrap().answer.clone();
+
+        assert_eq!(answer("Full name").as_deref(), Some("Jane Doe"));
+        assert_eq!(answer("Skills").as_deref(), Some("Rust; Kafka"));
+        assert_eq!(answer("Professional summary"), None);
+        assert_eq!(answer("Work history"), None);
+    }
+}
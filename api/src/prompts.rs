@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, UNIX_EPOCH};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tracing::{error, info};
+use crate::{db, language};
+use crate::tools::{Tool, ToolName};
+
+pub const DEFAULT_TENANT: &str = "default";
+
+const RELOAD_INTERVAL_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptName {
+    Profession,
+    Questions,
+    Answers,
+    Resume,
+    Summary,
+    Import,
+    Tailor,
+    CoverLetter,
+    Review,
+}
+
+impl PromptName {
+    pub const ALL: [PromptName; 9] = [
+        PromptName::Profession,
+        PromptName::Questions,
+        PromptName::Answers,
+        PromptName::Resume,
+        PromptName::Summary,
+        PromptName::Import,
+        PromptName::Tailor,
+        PromptName::CoverLetter,
+        PromptName::Review,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PromptName::Profession => "profession",
+            PromptName::Questions => "questions",
+            PromptName::Answers => "answers",
+            PromptName::Resume => "resume",
+            PromptName::Summary => "summary",
+            PromptName::Import => "import",
+            PromptName::Tailor => "tailor",
+            PromptName::CoverLetter => "cover_letter",
+            PromptName::Review => "review",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == name)
+    }
+
+    /// Tools offered to the model together with this prompt.
+    pub fn tools(&self) -> &'static [ToolName] {
+        match self {
+            PromptName::Profession => &[ToolName::SaveProfession],
+            PromptName::Questions => &[ToolName::AddQuestions],
+            PromptName::Answers | PromptName::Import => &[ToolName::SetAnswer, ToolName::AddJob, ToolName::AddEducation],
+            PromptName::Resume | PromptName::Tailor => &[ToolName::SaveResume],
+            PromptName::CoverLetter => &[ToolName::SaveCoverLetter],
+            PromptName::Review => &[ToolName::SetAnswer, ToolName::AddJob, ToolName::AddEducation, ToolName::RegenerateResume],
+            PromptName::Summary => &[],
+        }
+    }
+
+    fn builtin(&self) -> &'static str {
+        match self {
+            PromptName::Profession => include_str!("data/prompt_profession.txt"),
+            PromptName::Questions => include_str!("data/prompt_questions.txt"),
+            PromptName::Answers => include_str!("data/prompt_answers.txt"),
+            PromptName::Resume => include_str!("data/prompt_resume.txt"),
+            PromptName::Summary => include_str!("data/prompt_summary.txt"),
+            PromptName::Import => include_str!("data/prompt_import.txt"),
+            PromptName::Tailor => include_str!("data/prompt_tailor.txt"),
+            PromptName::CoverLetter => include_str!("data/prompt_cover_letter.txt"),
+            PromptName::Review => include_str!("data/prompt_review.txt"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Prompt {
+    pub name: PromptName,
+    /// Language of a localised variant; `None` for the variant used by every language.
+    pub language: Option<String>,
+    pub text: String,
+    pub version: String,
+}
+
+impl Prompt {
+    pub fn builtin(name: PromptName) -> Self {
+        Prompt {
+            name,
+            language: None,
+            text: name.builtin().to_string(),
+            version: format!("builtin:{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+}
+
+/// Prompts and tool schemas embedded at build time, overridden by files from `PROMPTS_DIR` and then by
+/// active rows of the `prompts` and `tool_schemas` tables. A tenant without its own override uses the
+/// `default` tenant's one, and a localised prompt variant wins over the language-neutral one.
+/// Overrides are re-read periodically, so edits apply without a restart.
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    overrides: Arc<RwLock<Overrides>>,
+}
+
+#[derive(Debug, Default)]
+struct Overrides {
+    /// Keyed by tenant, name and language, the language being empty for language-neutral prompts.
+    prompts: HashMap<(String, PromptName, String), Prompt>,
+    tools: HashMap<(String, ToolName), Tool>,
+}
+
+impl PromptRegistry {
+    pub fn new() -> Self {
+        PromptRegistry { overrides: Arc::new(RwLock::new(Overrides::default())) }
+    }
+
+    pub fn get(&self, tenant: &str, name: PromptName, language: Option<&str>) -> Prompt {
+        let overrides = self.overrides.read().expect("prompt registry lock poisoned");
+        [tenant, DEFAULT_TENANT].into_iter()
+            .flat_map(|t| [(t, language.unwrap_or_default()), (t, "")])
+            .find_map(|(t, l)| overrides.prompts.get(&(t.to_string(), name, l.to_string())))
+            .cloned()
+            .unwrap_or_else(|| Prompt::builtin(name))
+    }
+
+    pub fn get_tool(&self, tenant: &str, name: ToolName) -> Tool {
+        let overrides = self.overrides.read().expect("prompt registry lock poisoned");
+        [tenant, DEFAULT_TENANT].into_iter()
+            .find_map(|t| overrides.tools.get(&(t.to_string(), name)))
+            .cloned()
+            .unwrap_or_else(|| Tool::builtin(name))
+    }
+
+    pub fn get_tools(&self, tenant: &str, name: PromptName) -> Vec<Tool> {
+        name.tools().iter().map(|t| self.get_tool(tenant, *t)).collect()
+    }
+
+    pub async fn reload(&self, pool: &Pool<Postgres>) -> Result<(), &'static str> {
+        let mut overrides = Overrides::default();
+
+        if let Ok(dir) = env::var("PROMPTS_DIR") {
+            for prompt in load_dir(Path::new(&dir)).await {
+                let key = (DEFAULT_TENANT.to_string(), prompt.name, prompt.language.clone().unwrap_or_default());
+                overrides.prompts.insert(key, prompt);
+            }
+        }
+
+        let prompts = db::load_active_prompts(pool).await.map_err(|_| "Failed to load prompts")?;
+        for row in prompts {
+            if let Some(name) = PromptName::from_name(&row.name) {
+                let prompt = Prompt {
+                    name,
+                    language: Some(row.language.clone()).filter(|l| !l.is_empty()),
+                    text: row.text,
+                    version: format!("db:{}", row.version),
+                };
+                overrides.prompts.insert((row.tenant, name, row.language), prompt);
+            }
+        }
+
+        let tools = db::load_active_tools(pool).await.map_err(|_| "Failed to load tool schemas")?;
+        for row in tools {
+            if let Some(name) = ToolName::from_name(&row.name) {
+                let tool = Tool {
+                    name,
+                    description: row.description,
+                    parameters: row.parameters,
+                    version: format!("db:{}", row.version),
+                };
+                overrides.tools.insert((row.tenant, name), tool);
+            }
+        }
+
+        *self.overrides.write().expect("prompt registry lock poisoned") = overrides;
+        Ok(())
+    }
+
+    pub fn spawn_reloader(&self, pool: Pool<Postgres>) {
+        let registry = self.clone();
+        let secs = env::var("PROMPTS_RELOAD_SECS").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(RELOAD_INTERVAL_SECS);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(secs));
+            loop {
+                interval.tick().await;
+                if let Err(e) = registry.reload(&pool).await {
+                    error!("Failed to reload prompts: {e}");
+                }
+            }
+        });
+        info!("Prompts reload every {secs}s");
+    }
+}
+
+impl Default for PromptRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads `<dir>/prompt_<name>.txt` and localised `<dir>/prompt_<name>.<language>.txt`,
+/// using the file modification time as the version.
+async fn load_dir(dir: &Path) -> Vec<Prompt> {
+    let mut prompts = vec![];
+
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        error!("Failed to read prompts dir {}", dir.display());
+        return prompts;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = file_name.strip_prefix("prompt_").and_then(|f| f.strip_suffix(".txt")) else {
+            continue;
+        };
+        let (name, language) = match stem.split_once('.') {
+            Some((name, language)) => match language::normalize(language) {
+                Some(language) => (name, Some(language.to_string())),
+                None => continue,
+            },
+            None => (stem, None),
+        };
+        let Some(name) = PromptName::from_name(name) else {
+            continue;
+        };
+        let Ok(text) = tokio::fs::read_to_string(entry.path()).await else {
+            continue;
+        };
+        let modified = entry.metadata().await.ok()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+
+        prompts.push(Prompt { name, language, text, version: format!("file:{modified}") });
+    }
+
+    prompts
+}
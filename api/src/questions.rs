@@ -0,0 +1,90 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use crate::reply::{conflict, validation_error};
+use crate::user::{QuestionInput, QuestionPatch, User};
+use crate::AppState;
+
+/// Question indexes are stored as `u8`.
+const MAX_QUESTIONS: usize = u8::MAX as usize + 1;
+
+fn questions_reply(user: &User) -> Response {
+    Json(json!({
+        "stage": user.get_stage(),
+        "questions": user.get_questions(),
+    })).into_response()
+}
+
+fn validate_text(index: usize, text: &str, errors: &mut Vec<String>) {
+    if text.trim().is_empty() {
+        errors.push(format!("question {index} is empty"));
+    }
+}
+
+async fn save(app_state: &AppState, mut user: User) -> Response {
+    match user.save(&app_state.pool).await {
+        Ok(()) => questions_reply(&user),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn questions_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    match User::get_user(&app_state.pool, id).await {
+        Ok(Some(user)) => questions_reply(&user),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Replaces the whole survey: adding, removing and reordering questions.
+pub async fn questions_put(Path(id): Path<i32>, State(app_state): State<AppState>, Json(questions): Json<Vec<QuestionInput>>) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut errors = vec![];
+    if questions.is_empty() {
+        errors.push("at least one question is required".to_string());
+    }
+    if questions.len() > MAX_QUESTIONS {
+        errors.push(format!("no more than {MAX_QUESTIONS} questions are allowed"));
+    }
+    for (index, question) in questions.iter().enumerate() {
+        validate_text(index, &question.question, &mut errors);
+    }
+    if !errors.is_empty() {
+        return validation_error(errors);
+    }
+
+    match user.replace_questions(questions) {
+        Ok(()) => save(&app_state, user).await,
+        Err(e) => conflict(e),
+    }
+}
+
+/// Edits individual questions and answers by index.
+pub async fn questions_patch(Path(id): Path<i32>, State(app_state): State<AppState>, Json(patches): Json<Vec<QuestionPatch>>) -> Response {
+    let Ok(Some(mut user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut errors = vec![];
+    let total = user.get_questions().len();
+    for patch in &patches {
+        if patch.index as usize >= total {
+            errors.push(format!("question {} doesn't exist", patch.index));
+        }
+        if let Some(text) = &patch.question {
+            validate_text(patch.index as usize, text, &mut errors);
+        }
+    }
+    if !errors.is_empty() {
+        return validation_error(errors);
+    }
+
+    match user.update_questions(patches) {
+        Ok(()) => save(&app_state, user).await,
+        Err(e) => conflict(e),
+    }
+}
@@ -0,0 +1,24 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+/// Not possible in the current stage: `409 {"error": ...}`.
+pub fn conflict(error: &str) -> Response {
+    (StatusCode::CONFLICT, Json(json!({ "error": error }))).into_response()
+}
+
+/// The request is invalid: `422 {"errors": [...]}`.
+pub fn validation_error(errors: Vec<String>) -> Response {
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "errors": errors }))).into_response()
+}
+
+/// Like [`validation_error`], for a single reason.
+pub fn unprocessable(error: &str) -> Response {
+    validation_error(vec![error.to_string()])
+}
+
+/// The model didn't give a usable answer: `502 {"error": ...}`.
+pub fn bad_gateway(error: &str) -> Response {
+    (StatusCode::BAD_GATEWAY, Json(json!({ "error": error }))).into_response()
+}
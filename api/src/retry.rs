@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use tracing::warn;
+
+const MAX_RETRIES: u32 = 3;
+const BACKOFF_BASE_MS: u64 = 500;
+const BACKOFF_MAX_MS: u64 = 20_000;
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN_SECS: u64 = 30;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
+/// How LLM calls are retried, read from `LLM_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    fallback_models: Vec<String>,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        RetryPolicy {
+            max_retries: env_or("LLM_MAX_RETRIES", MAX_RETRIES),
+            base_delay: Duration::from_millis(env_or("LLM_BACKOFF_BASE_MS", BACKOFF_BASE_MS)),
+            max_delay: Duration::from_millis(env_or("LLM_BACKOFF_MAX_MS", BACKOFF_MAX_MS)),
+            fallback_models: env::var("LLM_FALLBACK_MODELS").unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// The requested model followed by the fallbacks, in order and without repeats.
+    pub fn models(&self, model: &str) -> Vec<String> {
+        let mut models = vec![model.to_string()];
+        for fallback in &self.fallback_models {
+            if !models.contains(fallback) {
+                models.push(fallback.clone());
+            }
+        }
+        models
+    }
+
+    /// Exponential backoff with full jitter; a server-provided `Retry-After` takes precedence.
+    /// Either way the delay is capped at `max_delay`.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let ceiling = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let jitter = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+        Duration::from_millis(jitter)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// When the trial call after the cooldown was let through; until it ends, other calls are refused.
+    probe_started: Option<Instant>,
+}
+
+fn cooldown() -> Duration {
+    Duration::from_secs(env_or("LLM_BREAKER_COOLDOWN_SECS", BREAKER_COOLDOWN_SECS))
+}
+
+fn breakers() -> &'static Mutex<HashMap<String, BreakerState>> {
+    static BREAKERS: OnceLock<Mutex<HashMap<String, BreakerState>>> = OnceLock::new();
+    BREAKERS.get_or_init(Default::default)
+}
+
+/// Per-model circuit breaker: after `LLM_BREAKER_THRESHOLD` consecutive transient failures
+/// the model is skipped for `LLM_BREAKER_COOLDOWN_SECS`, then a single trial call is let through.
+/// A trial call that never reports back, e.g. because the request was dropped, is replaced after another cooldown.
+pub struct CircuitBreaker;
+
+impl CircuitBreaker {
+    pub fn allows(model: &str) -> bool {
+        let mut breakers = breakers().lock().expect("circuit breaker lock poisoned");
+        let Some(breaker) = breakers.get_mut(model) else {
+            return true;
+        };
+        let Some(open_until) = breaker.open_until else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let probing = breaker.probe_started.is_some_and(|started| now < started + cooldown());
+        if now < open_until || probing {
+            return false;
+        }
+        breaker.probe_started = Some(now);
+        true
+    }
+
+    /// Closes the breaker; any answer from the model counts, as only transient failures open it.
+    pub fn success(model: &str) {
+        let mut breakers = breakers().lock().expect("circuit breaker lock poisoned");
+        breakers.remove(model);
+    }
+
+    /// Records a transient failure; returns `true` when the breaker is open afterwards.
+    pub fn failure(model: &str) -> bool {
+        let threshold = env_or("LLM_BREAKER_THRESHOLD", BREAKER_THRESHOLD);
+        let cooldown = cooldown();
+
+        let mut breakers = breakers().lock().expect("circuit breaker lock poisoned");
+        let breaker = breakers.entry(model.to_string()).or_default();
+        breaker.failures += 1;
+        if breaker.failures < threshold {
+            return false;
+        }
+
+        warn!("circuit breaker for {model} is open for {cooldown:?} after {} failures", breaker.failures);
+        breaker.open_until = Some(Instant::now() + cooldown);
+        breaker.probe_started = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: MAX_RETRIES,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            fallback_models: vec!["gpt-4o-mini".to_string(), "gpt-4o".to_string()],
+        }
+    }
+
+    /// Opens the breaker of a model, which no other test uses, and moves its cooldown into the past.
+    fn open_and_cool_down(model: &str) {
+        for _ in 0..BREAKER_THRESHOLD {
+            CircuitBreaker::failure(model);
+        }
+        let mut breakers = breakers().lock().unwrap();
+        breakers.get_mut(model).unwrap().open_until = Some(Instant::now() - Duration::from_secs(1));
+    }
+
+    #[test]
+    fn models_are_followed_by_the_fallbacks_once() {
+        assert_eq!(policy().models("gpt-4o"), ["gpt-4o", "gpt-4o-mini"]);
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_cap() {
+        let policy = policy();
+        for _ in 0..100 {
+            assert!(policy.delay(0, None) <= Duration::from_millis(100));
+            assert!(policy.delay(2, None) <= Duration::from_millis(400));
+            assert!(policy.delay(20, None) <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn retry_after_is_used_but_capped() {
+        let policy = policy();
+        assert_eq!(policy.delay(0, Some(Duration::from_millis(700))), Duration::from_millis(700));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(3600))), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn breaker_opens_after_the_threshold() {
+        let model = "test-opens";
+        for _ in 1..BREAKER_THRESHOLD {
+            assert!(!CircuitBreaker::failure(model));
+            assert!(CircuitBreaker::allows(model));
+        }
+        assert!(CircuitBreaker::failure(model));
+        assert!(!CircuitBreaker::allows(model));
+    }
+
+    #[test]
+    fn breaker_lets_a_single_trial_call_through_after_the_cooldown() {
+        let model = "test-half-open";
+        open_and_cool_down(model);
+        assert!(CircuitBreaker::allows(model));
+        assert!(!CircuitBreaker::allows(model));
+
+        CircuitBreaker::success(model);
+        assert!(CircuitBreaker::allows(model));
+        assert!(CircuitBreaker::allows(model));
+    }
+
+    #[test]
+    fn failed_trial_call_opens_the_breaker_again() {
+        let model = "test-probe-fails";
+        open_and_cool_down(model);
+        assert!(CircuitBreaker::allows(model));
+        assert!(CircuitBreaker::failure(model));
+        assert!(!CircuitBreaker::allows(model));
+    }
+
+    #[test]
+    fn trial_call_that_never_ends_is_replaced() {
+        let model = "test-probe-lost";
+        open_and_cool_down(model);
+        assert!(CircuitBreaker::allows(model));
+
+        breakers().lock().unwrap().get_mut(model).unwrap().probe_started = Some(Instant::now() - cooldown() - Duration::from_secs(1));
+        assert!(CircuitBreaker::allows(model));
+        assert!(!CircuitBreaker::allows(model));
+    }
+}
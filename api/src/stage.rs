@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+/// Where the user is in the interview; persisted in `users.stage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    #[default]
+    Profession,
+    Questions,
+    Answers,
+    Resume,
+    Done,
+    /// The model critiques the CV and collects what's missing to make it again.
+    Review,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Profession,
+        Stage::Questions,
+        Stage::Answers,
+        Stage::Resume,
+        Stage::Done,
+        Stage::Review,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Profession => "profession",
+            Stage::Questions => "questions",
+            Stage::Answers => "answers",
+            Stage::Resume => "resume",
+            Stage::Done => "done",
+            Stage::Review => "review",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.as_str() == name)
+    }
+}
+
+/// What moved the dialogue from one stage to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    ProfessionSaved,
+    QuestionsSaved,
+    AllAnswered,
+    QuestionsEdited,
+    ResumeSaved,
+    ReviewStarted,
+    Back,
+    Regenerate,
+    ChangeProfession,
+    Reset,
+    /// Restores a snapshot; not in the table, as it returns to whatever stage was saved.
+    Undo,
+}
+
+impl Trigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Trigger::ProfessionSaved => "profession_saved",
+            Trigger::QuestionsSaved => "questions_saved",
+            Trigger::AllAnswered => "all_answered",
+            Trigger::QuestionsEdited => "questions_edited",
+            Trigger::ResumeSaved => "resume_saved",
+            Trigger::ReviewStarted => "review_started",
+            Trigger::Back => "back",
+            Trigger::Regenerate => "regenerate",
+            Trigger::ChangeProfession => "change_profession",
+            Trigger::Reset => "reset",
+            Trigger::Undo => "undo",
+        }
+    }
+}
+
+/// Every allowed transition; anything else is rejected.
+/// `Reset` and `ChangeProfession` are allowed from any stage.
+const TRANSITIONS: &[(Stage, Trigger, Stage)] = &[
+    (Stage::Profession, Trigger::ProfessionSaved, Stage::Questions),
+    (Stage::Questions, Trigger::QuestionsSaved, Stage::Answers),
+    (Stage::Answers, Trigger::AllAnswered, Stage::Resume),
+    (Stage::Resume, Trigger::ResumeSaved, Stage::Done),
+    (Stage::Resume, Trigger::QuestionsEdited, Stage::Answers),
+    (Stage::Questions, Trigger::Back, Stage::Profession),
+    (Stage::Answers, Trigger::Back, Stage::Questions),
+    (Stage::Resume, Trigger::Back, Stage::Answers),
+    (Stage::Done, Trigger::Regenerate, Stage::Resume),
+    (Stage::Done, Trigger::ReviewStarted, Stage::Review),
+    (Stage::Review, Trigger::Regenerate, Stage::Resume),
+    (Stage::Review, Trigger::Back, Stage::Done),
+];
+
+pub fn next(from: Stage, trigger: Trigger) -> Option<Stage> {
+    if matches!(trigger, Trigger::Reset | Trigger::ChangeProfession) {
+        return Some(Stage::Profession);
+    }
+
+    TRANSITIONS.iter()
+        .find(|(f, t, _)| *f == from && *t == trigger)
+        .map(|(_, _, to)| *to)
+}
+
+/// A transition waiting to be written to `dialogue_events`.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: Stage,
+    pub to: Stage,
+    pub trigger: Trigger,
+    pub detail: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interview_goes_through_every_stage() {
+        let path = [
+            (Trigger::ProfessionSaved, Stage::Questions),
+            (Trigger::QuestionsSaved, Stage::Answers),
+            (Trigger::AllAnswered, Stage::Resume),
+            (Trigger::ResumeSaved, Stage::Done),
+            (Trigger::ReviewStarted, Stage::Review),
+            (Trigger::Regenerate, Stage::Resume),
+        ];
+        let mut stage = Stage::default();
+        for (trigger, to) in path {
+            stage = next(stage, trigger).unwrap_or_else(|| panic!("{} from {}", trigger.as_str(), stage.as_str()));
+            assert_eq!(stage, to);
+        }
+    }
+
+    #[test]
+    fn back_returns_to_the_previous_stage() {
+        assert_eq!(next(Stage::Questions, Trigger::Back), Some(Stage::Profession));
+        assert_eq!(next(Stage::Answers, Trigger::Back), Some(Stage::Questions));
+        assert_eq!(next(Stage::Resume, Trigger::Back), Some(Stage::Answers));
+        assert_eq!(next(Stage::Review, Trigger::Back), Some(Stage::Done));
+        assert_eq!(next(Stage::Profession, Trigger::Back), None);
+        assert_eq!(next(Stage::Done, Trigger::Back), None);
+    }
+
+    #[test]
+    fn reset_and_change_profession_are_allowed_from_any_stage() {
+        for stage in Stage::ALL {
+            assert_eq!(next(stage, Trigger::Reset), Some(Stage::Profession));
+            assert_eq!(next(stage, Trigger::ChangeProfession), Some(Stage::Profession));
+        }
+    }
+
+    #[test]
+    fn other_transitions_are_rejected() {
+        assert_eq!(next(Stage::Profession, Trigger::ResumeSaved), None);
+        assert_eq!(next(Stage::Answers, Trigger::Regenerate), None);
+        assert_eq!(next(Stage::Review, Trigger::ReviewStarted), None);
+        for stage in Stage::ALL {
+            assert_eq!(next(stage, Trigger::Undo), None);
+        }
+    }
+
+    #[test]
+    fn stages_round_trip_through_their_names() {
+        for stage in Stage::ALL {
+            assert_eq!(Stage::from_name(stage.as_str()), Some(stage));
+        }
+        assert_eq!(Stage::from_name("finished"), None);
+    }
+}
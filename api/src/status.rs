@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::Serialize;
+use crate::AppState;
+
+/// What the api is doing for a user right now.
+/// Ordered by progress, so the furthest of several requests is the one reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Idle,
+    /// Waiting for the model to answer a message.
+    Thinking,
+    /// Waiting for the model to write the CV, the longest call.
+    WritingCv,
+    /// Rendering the CV to PDF and storing it.
+    RenderingPdf,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Status {
+    step: Step,
+    /// Since the current request started, so clients can tell a slow generation from a stuck one.
+    seconds: u64,
+}
+
+/// Step and start of each request of a user, by request number.
+type Requests = HashMap<u64, (Step, Instant)>;
+
+/// Steps of the requests in progress, kept in memory: a restart loses them along with the requests.
+/// A user can have several at once, e.g. a message sent again while the first is still answered.
+#[derive(Debug, Clone, Default)]
+pub struct StatusRegistry {
+    running: Arc<RwLock<HashMap<i32, Requests>>>,
+    next_request: Arc<AtomicU64>,
+}
+
+impl StatusRegistry {
+    /// Marks the user as busy until the returned guard is dropped.
+    pub fn start(&self, user_id: i32) -> StatusGuard {
+        let request = self.next_request.fetch_add(1, Ordering::Relaxed);
+        self.running.write().expect("status lock poisoned")
+            .entry(user_id)
+            .or_default()
+            .insert(request, (Step::Thinking, Instant::now()));
+        StatusGuard { registry: self.clone(), user_id, request }
+    }
+
+    /// The request that got furthest, the user is idle once all have ended.
+    pub fn get(&self, user_id: i32) -> Status {
+        let running = self.running.read().expect("status lock poisoned");
+        let furthest = running.get(&user_id)
+            .and_then(|requests| requests.values().max_by_key(|(step, started)| (*step, std::cmp::Reverse(*started))));
+        match furthest {
+            Some((step, started)) => Status { step: *step, seconds: started.elapsed().as_secs() },
+            None => Status { step: Step::Idle, seconds: 0 },
+        }
+    }
+}
+
+/// Ends its own request when dropped, however it ends, leaving the user's other requests running.
+pub struct StatusGuard {
+    registry: StatusRegistry,
+    user_id: i32,
+    request: u64,
+}
+
+impl StatusGuard {
+    pub fn set(&self, step: Step) {
+        let mut running = self.registry.running.write().expect("status lock poisoned");
+        if let Some((current, _)) = running.get_mut(&self.user_id).and_then(|requests| requests.get_mut(&self.request)) {
+            *current = step;
+        }
+    }
+}
+
+impl Drop for StatusGuard {
+    fn drop(&mut self) {
+        let Ok(mut running) = self.registry.running.write() else {
+            return;
+        };
+        if let Some(requests) = running.get_mut(&self.user_id) {
+            requests.remove(&self.request);
+            if requests.is_empty() {
+                running.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+/// `GET /users/:id/status`, polled by clients while a message or command is processed.
+pub async fn status_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Json<Status> {
+    Json(app_state.status.get(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(registry: &StatusRegistry, user_id: i32) -> Step {
+        registry.get(user_id).step
+    }
+
+    #[test]
+    fn user_is_idle_once_the_request_ends() {
+        let registry = StatusRegistry::default();
+        let guard = registry.start(1);
+        assert_eq!(step(&registry, 1), Step::Thinking);
+        guard.set(Step::WritingCv);
+        assert_eq!(step(&registry, 1), Step::WritingCv);
+        assert_eq!(step(&registry, 2), Step::Idle);
+
+        drop(guard);
+        assert_eq!(step(&registry, 1), Step::Idle);
+    }
+
+    #[test]
+    fn overlapping_requests_end_separately() {
+        let registry = StatusRegistry::default();
+        let first = registry.start(1);
+        let second = registry.start(1);
+        second.set(Step::WritingCv);
+        assert_eq!(step(&registry, 1), Step::WritingCv);
+
+        // The first request ending must not hide the CV still being written.
+        drop(first);
+        assert_eq!(step(&registry, 1), Step::WritingCv);
+
+        second.set(Step::RenderingPdf);
+        assert_eq!(step(&registry, 1), Step::RenderingPdf);
+        drop(second);
+        assert_eq!(step(&registry, 1), Step::Idle);
+    }
+}
@@ -3,9 +3,12 @@ use std::path::Path;
 use aws_sdk_s3 as s3;
 use aws_sdk_s3::config::{Credentials, Region};
 use aws_sdk_s3::config::endpoint::{Endpoint, EndpointFuture, Params, ResolveEndpoint};
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::delete_object::DeleteObjectError;
 use aws_sdk_s3::primitives::ByteStream;
 use axum::body::Bytes;
 use s3::Client;
+use tracing::error;
 
 #[derive(Debug)]
 struct S3EndpointResolver {
@@ -68,13 +71,21 @@ pub async fn load(client: &Client, bucket_name: &str, src_name: &str) -> Result<
     Ok(obj.body.collect().await.expect("foo").into_bytes())
 }
 
-#[allow(dead_code)]
-pub async fn delete(client: &Client, bucket_name: &str, name: &str) -> Result<(), &'static str> {
+pub async fn delete(client: &Client, bucket_name: &str, name: &str) -> Result<(), SdkError<DeleteObjectError>> {
     client
             .delete_object()
             .bucket(bucket_name.to_string())
             .key(name)
             .send()
-            .await.expect("foo");
+            .await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Deletes an object nothing refers to any more; a failure only leaves garbage behind, so it's just logged.
+pub async fn delete_unused(client: &Client, bucket_name: &str, name: Option<String>) {
+    if let Some(name) = name {
+        if let Err(e) = delete(client, bucket_name, &name).await {
+            error!("Failed to delete {name}: {e:?}");
+        }
+    }
+}
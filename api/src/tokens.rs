@@ -0,0 +1,116 @@
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestUserMessageContent, ChatCompletionRequestMessageContentPart};
+use serde_json::Value;
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton};
+use tiktoken_rs::model::get_context_size;
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+// https://github.com/openai/openai-cookbook/blob/main/examples/How_to_count_tokens_with_tiktoken.ipynb
+const TOKENS_PER_MESSAGE: usize = 3;
+const TOKENS_PER_NAME: usize = 1;
+const TOKENS_REPLY_PRIMING: usize = 3;
+const TOKENS_PER_TOOL: usize = 8;
+const TOKENS_PER_IMAGE: usize = 85;
+
+pub fn context_window(model: &str) -> usize {
+    get_context_size(model)
+}
+
+pub fn count_text(model: &str, text: &str) -> usize {
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => o200k_base_singleton(),
+        _ => cl100k_base_singleton(),
+    };
+    let tokens = bpe.lock().encode_with_special_tokens(text).len();
+    tokens
+}
+
+/// The longest prefix of the text, cut at a character boundary, that fits in `max_tokens`.
+pub fn truncate_text(model: &str, text: &str, max_tokens: usize) -> String {
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    let (mut low, mut high) = (0, boundaries.len() - 1);
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        match count_text(model, &text[..boundaries[middle]]) <= max_tokens {
+            true => low = middle,
+            false => high = middle - 1,
+        }
+    }
+    text[..boundaries[low]].to_string()
+}
+
+/// Shortens the text of a user or system message so the whole message fits in `max_tokens`;
+/// other messages are returned as they are, as their content can't be cut safely.
+pub fn truncate_message(model: &str, message: &ChatCompletionRequestMessage, max_tokens: usize) -> ChatCompletionRequestMessage {
+    let overhead = count_message(model, message) - count_text(model, &message_text(message));
+    let max_text = max_tokens.saturating_sub(overhead);
+
+    let mut message = message.clone();
+    match &mut message {
+        ChatCompletionRequestMessage::System(sm) => sm.content = truncate_text(model, &sm.content, max_text),
+        ChatCompletionRequestMessage::User(um) => {
+            if let ChatCompletionRequestUserMessageContent::Text(t) = &um.content {
+                um.content = ChatCompletionRequestUserMessageContent::Text(truncate_text(model, t, max_text));
+            }
+        }
+        _ => {}
+    }
+    message
+}
+
+pub fn message_text(message: &ChatCompletionRequestMessage) -> String {
+    match message {
+        ChatCompletionRequestMessage::System(sm) => sm.content.clone(),
+        ChatCompletionRequestMessage::User(um) => match &um.content {
+            ChatCompletionRequestUserMessageContent::Text(t) => t.clone(),
+            ChatCompletionRequestUserMessageContent::Array(parts) => parts.iter()
+                .filter_map(|part| match part {
+                    ChatCompletionRequestMessageContentPart::Text(t) => Some(t.text.clone()),
+                    ChatCompletionRequestMessageContentPart::Image(_) => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        },
+        ChatCompletionRequestMessage::Assistant(am) => am.content.clone().unwrap_or_default(),
+        ChatCompletionRequestMessage::Tool(tm) => tm.content.clone(),
+        ChatCompletionRequestMessage::Function(fm) => fm.content.clone().unwrap_or_default(),
+    }
+}
+
+pub fn count_message(model: &str, message: &ChatCompletionRequestMessage) -> usize {
+    let mut tokens = TOKENS_PER_MESSAGE + TOKENS_PER_NAME + count_text(model, &message_text(message));
+
+    match message {
+        ChatCompletionRequestMessage::Assistant(am) => {
+            for tool_call in am.tool_calls.iter().flatten() {
+                tokens += TOKENS_PER_MESSAGE
+                    + count_text(model, &tool_call.function.name)
+                    + count_text(model, &tool_call.function.arguments);
+            }
+        }
+        ChatCompletionRequestMessage::User(um) => {
+            if let ChatCompletionRequestUserMessageContent::Array(parts) = &um.content {
+                tokens += parts.iter()
+                    .filter(|part| matches!(part, ChatCompletionRequestMessageContentPart::Image(_)))
+                    .count() * TOKENS_PER_IMAGE;
+            }
+        }
+        _ => {}
+    }
+
+    tokens
+}
+
+pub fn count_messages(model: &str, messages: &[ChatCompletionRequestMessage]) -> usize {
+    messages.iter().map(|m| count_message(model, m)).sum::<usize>() + TOKENS_REPLY_PRIMING
+}
+
+pub fn count_tools(model: &str, raw_functions: &[(&str, &str, Value)]) -> usize {
+    raw_functions.iter()
+        .map(|(name, description, parameters)| {
+            TOKENS_PER_TOOL
+                + count_text(model, name)
+                + count_text(model, description)
+                + count_text(model, &parameters.to_string())
+        })
+        .sum()
+}
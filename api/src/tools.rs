@@ -0,0 +1,258 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::entry::{job_schema, study_schema};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolName {
+    SaveProfession,
+    AddQuestions,
+    SetAnswer,
+    AddJob,
+    AddEducation,
+    SaveResume,
+    SaveCoverLetter,
+    RegenerateResume,
+}
+
+impl ToolName {
+    pub const ALL: [ToolName; 8] = [
+        ToolName::SaveProfession,
+        ToolName::AddQuestions,
+        ToolName::SetAnswer,
+        ToolName::AddJob,
+        ToolName::AddEducation,
+        ToolName::SaveResume,
+        ToolName::SaveCoverLetter,
+        ToolName::RegenerateResume,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolName::SaveProfession => "save_profession",
+            ToolName::AddQuestions => "add_questions",
+            ToolName::SetAnswer => "set_answer",
+            ToolName::AddJob => "add_job",
+            ToolName::AddEducation => "add_education",
+            ToolName::SaveResume => "save_resume",
+            ToolName::SaveCoverLetter => "save_cover_letter",
+            ToolName::RegenerateResume => "regenerate_resume",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == name)
+    }
+
+    /// Arguments the dialogue reads from the tool call, with their JSON type.
+    /// Every edited schema has to keep them.
+    fn expected_arguments(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            ToolName::SaveProfession => &[("profession", "string")],
+            ToolName::AddQuestions => &[("questions", "array")],
+            ToolName::SetAnswer => &[("index", "integer"), ("answer", "string")],
+            ToolName::AddJob => &[("index", "integer"), ("employer", "string"), ("title", "string")],
+            ToolName::AddEducation => &[("index", "integer"), ("institution", "string"), ("degree", "string")],
+            ToolName::SaveResume => &[("cv_html", "string")],
+            ToolName::SaveCoverLetter => &[("letter_html", "string")],
+            ToolName::RegenerateResume => &[("changes", "string")],
+        }
+    }
+
+    fn builtin(&self) -> (&'static str, Value) {
+        match self {
+            ToolName::SaveProfession => ("Save the profession", json!({
+                "type": "object",
+                "properties": {
+                    "profession": {
+                        "type": "string",
+                        "description": "Name of profession, e.g. Software Developer",
+                    },
+                },
+                "required": ["profession"],
+            })),
+            ToolName::AddQuestions => ("Set a list of questions for creating a resume for a profession", json!({
+                "type": "object",
+                "properties": {
+                    "questions": {
+                        "type": "array",
+                        "description": "A list of questions",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "question": {
+                                    "type": "string",
+                                    "description": "Question, e.g. “full name”, “gender”, “name”, “work experience”, “list of programming languages studied”, “knowledge of the Django framework and at what level”",
+                                },
+                                "type": {
+                                    "type": "string",
+                                    "enum": ["text", "date", "list", "select", "work_history", "education"],
+                                    "description": "Type of the answer",
+                                },
+                                "options": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Possible answers for the \"select\" type",
+                                },
+                                "section": {
+                                    "type": "string",
+                                    "enum": ["personal", "summary", "experience", "education", "skills", "achievements", "development", "additional", "references"],
+                                    "description": "Section of the resume the question is for",
+                                },
+                                "required": {
+                                    "type": "boolean",
+                                    "description": "Whether the resume can't be made without the answer",
+                                },
+                                "hint": {
+                                    "type": "string",
+                                    "description": "A short example or explanation of the expected answer",
+                                },
+                            },
+                            "required": ["question", "type", "section", "required"],
+                        },
+                        "minItems": 5,
+                        "maxItems": 20,
+                    },
+                },
+                "required": ["questions"],
+            })),
+            ToolName::SetAnswer => ("Set answer to the survey question by index", json!({
+                "type": "object",
+                "properties": {
+                    "index": {
+                        "type": "integer",
+                        "description": "index question from the survey",
+                    },
+                    "answer": {
+                        "type": "string",
+                        "description": "answer to the survey question",
+                    },
+                },
+                "required": ["index", "answer"],
+            })),
+            ToolName::AddJob => ("Add a job to a \"work_history\" question by index", with_index(job_schema())),
+            ToolName::AddEducation => ("Add a degree or course to an \"education\" question by index", with_index(study_schema())),
+            ToolName::SaveResume => ("Save the CV HTML summary", json!({
+                "type": "object",
+                "properties": {
+                    "cv_html": {
+                        "type": "string",
+                        "description": "the HTML of CV"
+                    },
+                },
+                "required": ["cv_html"],
+            })),
+            ToolName::SaveCoverLetter => ("Save the cover letter HTML", json!({
+                "type": "object",
+                "properties": {
+                    "letter_html": {
+                        "type": "string",
+                        "description": "the HTML of the cover letter"
+                    },
+                },
+                "required": ["letter_html"],
+            })),
+            ToolName::RegenerateResume => ("Make the CV again from the improved answers, once the user agrees", json!({
+                "type": "object",
+                "properties": {
+                    "changes": {
+                        "type": "string",
+                        "description": "what was improved in the answers, in a sentence",
+                    },
+                },
+                "required": ["changes"],
+            })),
+        }
+    }
+}
+
+/// Adds the index of the survey question an entry belongs to.
+fn with_index(mut schema: Value) -> Value {
+    schema["properties"]["index"] = json!({
+        "type": "integer",
+        "description": "index question from the survey",
+    });
+    schema["required"].as_array_mut().expect("entry schema has required fields").insert(0, json!("index"));
+    schema
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tool {
+    pub name: ToolName,
+    pub description: String,
+    pub parameters: Value,
+    pub version: String,
+}
+
+impl Tool {
+    pub fn builtin(name: ToolName) -> Self {
+        let (description, parameters) = name.builtin();
+        Tool {
+            name,
+            description: description.to_string(),
+            parameters,
+            version: format!("builtin:{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    pub fn as_raw(&self) -> (&str, &str, Value) {
+        (self.name.as_str(), &self.description, self.parameters.clone())
+    }
+}
+
+/// Parses the arguments of a tool call and checks them against the tool's schema.
+pub fn validate_arguments(tool: &Tool, arguments: &str) -> Result<Value, Vec<String>> {
+    let arguments: Value = serde_json::from_str(arguments)
+        .map_err(|e| vec![format!("arguments are not valid JSON: {e}")])?;
+    let schema = jsonschema::JSONSchema::compile(&tool.parameters)
+        .map_err(|e| vec![format!("schema of \"{}\" is invalid: {e}", tool.name.as_str())])?;
+
+    let errors: Vec<String> = match schema.validate(&arguments) {
+        Ok(()) => vec![],
+        Err(errors) => errors
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{path}: {e}"),
+            })
+            .collect(),
+    };
+
+    match errors.is_empty() {
+        true => Ok(arguments),
+        false => Err(errors),
+    }
+}
+
+/// Checks that `parameters` is a valid JSON schema for an object that still provides
+/// every argument the dialogue relies on.
+pub fn validate_parameters(name: ToolName, parameters: &Value) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+
+    if let Err(e) = jsonschema::JSONSchema::compile(parameters) {
+        errors.push(format!("invalid JSON schema: {e}"));
+    }
+
+    if parameters["type"] != "object" {
+        errors.push("\"type\" must be \"object\"".to_string());
+    }
+
+    let required: Vec<&str> = parameters["required"].as_array()
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    for (field, field_type) in name.expected_arguments() {
+        match parameters["properties"].get(field) {
+            Some(property) if property["type"] == *field_type => {}
+            Some(_) => errors.push(format!("property \"{field}\" must be of type \"{field_type}\"")),
+            None => errors.push(format!("property \"{field}\" is missing")),
+        }
+        if !required.contains(field) {
+            errors.push(format!("property \"{field}\" must be required"));
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(()),
+        false => Err(errors),
+    }
+}
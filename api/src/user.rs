@@ -1,94 +1,413 @@
-use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageContent};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use async_openai::types::{ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs};
 use derivative::Derivative;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 use sqlx::{Pool, Postgres};
+use tracing::warn;
 use crate::db;
+use crate::entry::{self, Entry};
 use crate::message::Message;
+use crate::openai::TokenUsage;
+use crate::profile::Profile;
+use crate::prompts::DEFAULT_TENANT;
+use crate::{language, stage, tokens};
+use crate::stage::{Stage, Transition, Trigger};
 
-#[derive(Debug)]
-pub enum Need {
-    Profession,
-    Questions,
-    Answers,
-    Resume,
-    None,
+/// How a question is answered, chosen by the model; form clients render fields by it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerType {
+    #[default]
+    Text,
+    Date,
+    List,
+    Select,
+    /// Jobs, collected as entries with `add_job`.
+    WorkHistory,
+    /// Degrees and courses, collected as entries with `add_education`.
+    Education,
+}
+
+/// Part of the CV a question belongs to; the survey is asked section by section, in this order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    Personal,
+    Summary,
+    Experience,
+    Education,
+    Skills,
+    Achievements,
+    Development,
+    #[default]
+    Additional,
+    References,
+}
+
+/// Whether answers are collected in a chat or with a form.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    #[default]
+    Chat,
+    Form,
+}
+
+/// What [`User::back`] undid, so the reply can be written in the user's language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Back {
+    /// The question with this index and text is open again.
+    Reopened(u8, String),
+    QuestionsDiscarded,
+    ProfessionCleared,
+    ReviewClosed,
+}
+
+impl Mode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Mode::Chat => "chat",
+            Mode::Form => "form",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Mode::Chat, Mode::Form].into_iter().find(|m| m.as_str() == name)
+    }
 }
 
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(Debug, Clone, Default)]
-struct Question {
+pub struct Question {
     index: u8,
     question: String,
+    #[serde(rename = "type", default)]
+    answer_type: AnswerType,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    options: Vec<String>,
+    #[serde(default)]
+    section: Section,
+    /// Optional questions don't hold up the CV.
+    #[serde(default = "default_required")]
+    required: bool,
+    /// What a good answer looks like, shown to the user along with the question.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hint: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<Entry>,
     // #[serde(skip_serializing_if = "Option::is_none")]
     answer: Option<String>,
+    /// The user chose not to answer; the CV leaves the question out.
+    #[serde(default)]
+    skipped: bool,
 }
 
 impl Question {
-    fn new(index: u8, question: &str) -> Self {
+    fn new(index: u8, input: QuestionInput) -> Self {
         Question {
             index,
-            question: question.to_string(),
-            answer: None,
+            question: input.question,
+            answer_type: input.answer_type,
+            options: input.options,
+            section: input.section,
+            required: input.required,
+            hint: input.hint,
+            entries: input.entries,
+            skipped: input.skipped && input.answer.is_none(),
+            answer: input.answer,
         }
     }
 
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    pub fn question(&self) -> &str {
+        &self.question
+    }
+
+    pub fn answer_type(&self) -> AnswerType {
+        self.answer_type
+    }
+
+    pub fn options(&self) -> &[String] {
+        &self.options
+    }
+
+    pub fn section(&self) -> Section {
+        self.section
+    }
+
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn answer(&self) -> Option<&str> {
+        self.answer.as_deref()
+    }
+
     fn set_answer(&mut self, answer: &str) {
         self.answer = Some(answer.to_string());
+        self.skipped = false;
+    }
+
+    /// Entries answer a question only once the model closes it with `set_answer`, so the user can add more.
+    fn add_entry(&mut self, entry: Entry) -> Result<(), &'static str> {
+        match (self.answer_type, &entry) {
+            (AnswerType::WorkHistory, Entry::Job(_)) | (AnswerType::Education, Entry::Study(_)) => {
+                self.entries.push(entry);
+                self.skipped = false;
+                Ok(())
+            }
+            _ => Err("the question doesn't take this kind of entry"),
+        }
+    }
+
+    /// Answers with the entries, or skips the question when there are none.
+    fn set_entries(&mut self, entries: Vec<Entry>) {
+        self.answer = entry::answer(&entries);
+        self.skipped = entries.is_empty();
+        self.entries = entries;
+    }
+
+    fn skip(&mut self) {
+        self.answer = None;
+        self.skipped = true;
+    }
+
+    fn is_open(&self) -> bool {
+        self.answer.is_none() && !self.skipped
+    }
+
+    /// Only required questions have to be dealt with before the CV.
+    fn is_blocking(&self) -> bool {
+        self.required && self.is_open()
+    }
+}
+
+fn default_required() -> bool {
+    true
+}
+
+/// The model answers "N/A" for whatever the user can't or won't answer.
+fn is_not_applicable(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "n/a" | "na" | "-")
+}
+
+/// A new question, as generated by the model or sent by a client editing the survey directly.
+#[derive(Debug, Deserialize)]
+pub struct QuestionInput {
+    pub question: String,
+    #[serde(rename = "type", default)]
+    pub answer_type: AnswerType,
+    #[serde(default)]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub section: Section,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    #[serde(default)]
+    pub hint: Option<String>,
+    #[serde(default)]
+    pub entries: Vec<Entry>,
+    #[serde(default)]
+    pub answer: Option<String>,
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+impl QuestionInput {
+    pub fn new(question: &str) -> Self {
+        QuestionInput {
+            question: question.to_string(),
+            answer_type: AnswerType::Text,
+            options: vec![],
+            section: Section::default(),
+            required: true,
+            hint: None,
+            entries: vec![],
+            answer: None,
+            skipped: false,
+        }
     }
 }
 
+/// Changes to one question; absent fields are kept, `"answer": null` clears the answer.
+#[derive(Debug, Deserialize)]
+pub struct QuestionPatch {
+    pub index: u8,
+    pub question: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub answer: Option<Option<String>>,
+    pub skipped: Option<bool>,
+    pub section: Option<Section>,
+    pub required: Option<bool>,
+    pub hint: Option<String>,
+    /// Replaces the entries and the answer along with them.
+    pub entries: Option<Vec<Entry>>,
+}
+
+/// A submitted form field: text, or the entries of a work history or education question.
+#[derive(Debug)]
+pub enum FormAnswer {
+    Text(String),
+    Entries(Vec<Entry>),
+}
+
+fn double_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::deserialize(deserializer).map(Some)
+}
+
+/// Dialogue state as it was before a stage transition, to be restored by an undo.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub stage: String,
+    pub profession: Option<String>,
+    pub questions: Option<Value>,
+    pub messages: Value,
+    pub summary: Option<String>,
+    pub summarized_messages: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Progress {
+    pub stage: Stage,
+    pub profession: Option<String>,
+    pub answered: usize,
+    pub skipped: usize,
+    pub total: usize,
+    /// Required questions still without an answer.
+    pub required_left: usize,
+    pub next_question: Option<String>,
+}
+
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(Debug, Default)]
 pub struct User {
     pub id: u64,
+    #[derivative(Default(value = "DEFAULT_TENANT.to_string()"))]
+    tenant: String,
+    stage: Stage,
+    mode: Mode,
+    language: Option<String>,
+    cv_language: Option<String>,
     profession: Option<String>,
     questions: Option<Vec<Question>>,
     resume: Option<String>,
+    resume_prompt_version: Option<String>,
+    /// The CV as generated, kept for checks that need its markup.
+    resume_html: Option<String>,
+    cover_letter: Option<String>,
+    cover_letter_prompt_version: Option<String>,
+    /// Name of the profile photo in the bucket, put into every CV.
+    photo: Option<String>,
     messages: Vec<ChatCompletionRequestMessage>,
+    summary: Option<String>,
+    summarized_messages: u32,
     tokens_spent: u32,
+    prompt_tokens_spent: u32,
+    completion_tokens_spent: u32,
+    /// Stage changes not yet written to `dialogue_events`.
+    #[serde(skip)]
+    transitions: Vec<Transition>,
+    /// States before those changes, not yet written to `dialogue_snapshots`.
+    #[serde(skip)]
+    snapshots: Vec<Snapshot>,
 }
 
 #[derive(Derivative, Deserialize, Serialize)]
 #[derivative(Debug, Default)]
 pub struct UserWithCustomMessages {
     pub id: i32,
+    pub tenant: String,
+    pub stage: String,
+    pub mode: String,
+    pub language: Option<String>,
+    pub cv_language: Option<String>,
     pub profession: Option<String>,
     pub questions: Option<Value>,
     pub resume: Option<String>,
+    pub resume_prompt_version: Option<String>,
+    pub resume_html: Option<String>,
+    pub cover_letter: Option<String>,
+    pub cover_letter_prompt_version: Option<String>,
+    pub photo: Option<String>,
     pub messages: Value,
+    pub summary: Option<String>,
+    pub summarized_messages: i32,
     pub tokens_spent: i32,
+    pub prompt_tokens_spent: i32,
+    pub completion_tokens_spent: i32,
 }
 
 impl UserWithCustomMessages {
     pub fn from_original(user: &User) -> Self {
-        let messages = serde_json::to_value(user.messages.iter().map(|msg| Message::from_original(msg.clone())).collect::<Vec<_>>()).unwrap_or_default();
+        let messages = messages_to_value(&user.messages);
         let questions = user.questions.as_ref()
             .map(|qs| serde_json::to_value(qs).unwrap_or_default());
 
         UserWithCustomMessages {
             id: user.id as i32,
+            tenant: user.tenant.clone(),
+            stage: user.stage.as_str().to_string(),
+            mode: user.mode.as_str().to_string(),
+            language: user.language.clone(),
+            cv_language: user.cv_language.clone(),
             profession: user.profession.clone(),
             questions,
             resume: user.resume.clone(),
+            resume_prompt_version: user.resume_prompt_version.clone(),
+            resume_html: user.resume_html.clone(),
+            cover_letter: user.cover_letter.clone(),
+            cover_letter_prompt_version: user.cover_letter_prompt_version.clone(),
+            photo: user.photo.clone(),
             messages,
+            summary: user.summary.clone(),
+            summarized_messages: user.summarized_messages as i32,
             tokens_spent: user.tokens_spent as i32,
+            prompt_tokens_spent: user.prompt_tokens_spent as i32,
+            completion_tokens_spent: user.completion_tokens_spent as i32,
         }
     }
 
     pub fn into_original(self) -> User {
-        let messages = serde_json::from_value::<Vec<Message>>(self.messages).unwrap_or_default()
-            .into_iter().map(|msg| msg.into_original()).collect();
+        let messages = messages_from_value(self.messages);
         let questions = self.questions.as_ref()
             .and_then(|qs| serde_json::from_value(qs.clone()).ok());
 
         User {
             id: self.id as u64,
+            tenant: self.tenant,
+            stage: Stage::from_name(&self.stage).unwrap_or_default(),
+            mode: Mode::from_name(&self.mode).unwrap_or_default(),
+            language: self.language,
+            cv_language: self.cv_language,
             profession: self.profession,
             questions,
             resume: self.resume,
+            resume_prompt_version: self.resume_prompt_version,
+            resume_html: self.resume_html,
+            cover_letter: self.cover_letter,
+            cover_letter_prompt_version: self.cover_letter_prompt_version,
+            photo: self.photo,
             messages,
+            summary: self.summary,
+            summarized_messages: self.summarized_messages as u32,
             tokens_spent: self.tokens_spent as u32,
+            prompt_tokens_spent: self.prompt_tokens_spent as u32,
+            completion_tokens_spent: self.completion_tokens_spent as u32,
+            transitions: vec![],
+            snapshots: vec![],
         }
     }
 }
@@ -102,115 +421,563 @@ impl User {
     }
 
     pub fn new(id: u64) -> Self {
-        let mut u = User::default();
-        u.id = id;
-
-        u
+        User { id, ..Default::default() }
     }
 
-    pub async fn create_user(pool: &Pool<Postgres>) -> Result<User, &'static str> {
-        match db::new_user(pool).await {
-            Ok(id) => Ok(User::new(id)),
+    pub async fn create_user(pool: &Pool<Postgres>, tenant: &str, mode: Mode) -> Result<User, &'static str> {
+        match db::new_user(pool, tenant, mode.as_str()).await {
+            Ok(id) => Ok(User { tenant: tenant.to_string(), mode, ..User::new(id) }),
             _ => panic!("foo")
         }
     }
 
-    pub fn need(&self) -> Need {
-        if self.resume.is_some() {
-            return Need::None;
+    pub fn get_tenant(&self) -> String {
+        self.tenant.clone()
+    }
+
+    pub fn get_stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Moves to the next stage if the transition table allows it, queueing an audit event.
+    fn transition(&mut self, trigger: Trigger, detail: Option<String>) -> Result<(), &'static str> {
+        let Some(to) = stage::next(self.stage, trigger) else {
+            warn!("user {}: {} is not allowed in stage {}", self.id, trigger.as_str(), self.stage.as_str());
+            return Err("transition not allowed in the current stage");
+        };
+
+        self.snapshots.push(self.snapshot());
+        self.transitions.push(Transition { from: self.stage, to, trigger, detail });
+        self.stage = to;
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            stage: self.stage.as_str().to_string(),
+            profession: self.profession.clone(),
+            questions: self.questions.as_ref().map(|qs| serde_json::to_value(qs).unwrap_or_default()),
+            messages: messages_to_value(&self.messages),
+            summary: self.summary.clone(),
+            summarized_messages: self.summarized_messages as i32,
+        }
+    }
+
+    /// Returns to the state saved before the last transition. A CV isn't part of a snapshot: a finished or
+    /// reviewed dialogue keeps the current CV, which is the one it was made with, and only loses the messages
+    /// that came after. Without a CV it comes back at the resume stage and the CV is made again;
+    /// returns the file name of the CV that no longer belongs to the state.
+    pub fn undo(&mut self, snapshot: db::DialogueSnapshot) -> Option<String> {
+        let stage = Stage::from_name(&snapshot.stage).unwrap_or_default();
+        let keeps_resume = matches!(stage, Stage::Done | Stage::Review) && self.resume.is_some();
+        let stage = match stage {
+            Stage::Done | Stage::Review if !keeps_resume => Stage::Resume,
+            stage => stage,
+        };
+
+        self.transitions.push(Transition { from: self.stage, to: stage, trigger: Trigger::Undo, detail: None });
+        self.stage = stage;
+        self.profession = snapshot.profession;
+        self.questions = snapshot.questions.and_then(|qs| serde_json::from_value(qs).ok());
+        self.messages = messages_from_value(snapshot.messages);
+        self.summary = snapshot.summary;
+        self.summarized_messages = snapshot.summarized_messages as u32;
+        if keeps_resume {
+            return None;
         }
+        self.resume_prompt_version = None;
+        self.resume_html = None;
+        self.resume.take()
+    }
 
-        if let Some(questions) = &self.questions {
-            return match questions.iter().all(|q| q.answer.is_some()) {
-                true => Need::Resume,
-                false => Need::Answers,
-            };
+    fn all_answered(&self) -> bool {
+        self.questions.as_ref().is_some_and(|qs| qs.iter().all(|q| !q.is_blocking()))
+    }
+
+    /// Leaves a note for the model so the conversation stays consistent with a command.
+    pub fn add_note(&mut self, note: &str) {
+        self.add_message(
+            ChatCompletionRequestMessage::System(
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(note)
+                    .build().unwrap()
+            )
+        );
+    }
+
+    pub fn get_questions(&self) -> &[Question] {
+        self.questions.as_deref().unwrap_or_default()
+    }
+
+    fn can_edit_questions(&self) -> Result<(), &'static str> {
+        match self.stage {
+            Stage::Profession => Err("set the profession first"),
+            Stage::Done | Stage::Review => Err("the CV is ready, use regenerate, back or undo first"),
+            Stage::Questions | Stage::Answers | Stage::Resume => Ok(()),
+        }
+    }
+
+    /// Replaces the survey; questions are numbered in the given order.
+    pub fn replace_questions(&mut self, questions: Vec<QuestionInput>) -> Result<(), &'static str> {
+        self.can_edit_questions()?;
+        if self.stage == Stage::Questions {
+            self.transition(Trigger::QuestionsSaved, Some(format!("{} questions, edited", questions.len())))?;
+        }
+
+        self.questions = Some(new_questions(questions));
+        self.questions_edited()
+    }
+
+    /// Takes a submitted form as final: every question without an answer counts as skipped.
+    pub fn submit_form(&mut self, mut answers: BTreeMap<u8, FormAnswer>) -> Result<(), &'static str> {
+        if !matches!(self.stage, Stage::Answers | Stage::Resume) {
+            return Err("there is no form to fill in right now");
+        }
+
+        for question in self.questions.iter_mut().flatten() {
+            match answers.remove(&question.index) {
+                Some(FormAnswer::Text(answer)) => question.set_answer(&answer),
+                Some(FormAnswer::Entries(entries)) => question.set_entries(entries),
+                None => question.skip(),
+            }
+        }
+        self.questions_edited()
+    }
+
+    /// Applies the changes to individual questions; nothing is changed if any index is unknown.
+    pub fn update_questions(&mut self, patches: Vec<QuestionPatch>) -> Result<(), &'static str> {
+        self.can_edit_questions()?;
+        let questions = self.questions.as_mut().ok_or("no questions")?;
+        if patches.iter().any(|p| p.index as usize >= questions.len()) {
+            return Err("invalid question index");
+        }
+
+        for patch in patches {
+            let question = &mut questions[patch.index as usize];
+            if let Some(text) = patch.question {
+                question.question = text;
+            }
+            if let Some(skipped) = patch.skipped {
+                question.skipped = skipped;
+            }
+            if let Some(section) = patch.section {
+                question.section = section;
+            }
+            if let Some(required) = patch.required {
+                question.required = required;
+            }
+            if let Some(hint) = patch.hint {
+                question.hint = Some(hint).filter(|h| !h.trim().is_empty());
+            }
+            if let Some(entries) = patch.entries {
+                question.set_entries(entries);
+            }
+            if let Some(answer) = patch.answer {
+                question.answer = answer;
+            }
+            if question.answer.is_some() {
+                question.skipped = false;
+            }
+        }
+        self.questions_edited()
+    }
+
+    /// Keeps the stage in line with the answers after a direct edit and tells the model about it.
+    fn questions_edited(&mut self) -> Result<(), &'static str> {
+        match (self.stage, self.all_answered()) {
+            (Stage::Answers, true) => self.transition(Trigger::AllAnswered, Some("edited".to_string()))?,
+            (Stage::Resume, false) => self.transition(Trigger::QuestionsEdited, None)?,
+            _ => {}
+        }
+
+        let progress = self.progress();
+        self.add_note(&format!(
+            "The user edited the survey directly: {} questions, {} answered, {} skipped. \
+            Go on from the current answers and don't ask about answered questions again.",
+            progress.total, progress.answered, progress.skipped,
+        ));
+        Ok(())
+    }
+
+    pub fn progress(&self) -> Progress {
+        let questions = self.questions.as_deref().unwrap_or_default();
+        Progress {
+            stage: self.stage,
+            profession: self.profession.clone(),
+            answered: questions.iter().filter(|q| q.answer.is_some()).count(),
+            skipped: questions.iter().filter(|q| q.skipped).count(),
+            total: questions.len(),
+            required_left: questions.iter().filter(|q| q.is_blocking()).count(),
+            next_question: questions.iter()
+                .find(|q| q.is_blocking())
+                .or_else(|| questions.iter().find(|q| q.is_open()))
+                .map(|q| q.question.clone()),
+        }
+    }
+
+    /// Skips the given question, or the first open one; returns the skipped index.
+    pub fn skip_question(&mut self, index: Option<u8>) -> Result<u8, &'static str> {
+        if self.stage != Stage::Answers {
+            return Err("there is no question to skip right now");
         }
 
-        if self.profession.is_some() {
-            return Need::Questions;
+        let questions = self.questions.as_mut().ok_or("no questions")?;
+        let question = match index {
+            Some(index) => questions.get_mut(index as usize).ok_or("invalid question index")?,
+            None => questions.iter_mut().find(|q| q.is_open()).ok_or("no open questions")?,
+        };
+        question.skip();
+        let (index, text) = (question.index, question.question.clone());
+
+        self.add_note(&format!("The user skipped question {index} \"{text}\". Don't ask it again."));
+        if self.all_answered() {
+            self.transition(Trigger::AllAnswered, Some("skipped".to_string()))?;
         }
+        Ok(index)
+    }
+
+    /// Undoes the last step: reopens the last answered question, or returns to the previous stage.
+    pub fn back(&mut self) -> Result<Back, &'static str> {
+        match self.stage {
+            Stage::Answers | Stage::Resume => {
+                let last = self.questions.as_mut()
+                    .and_then(|qs| qs.iter_mut().rev().find(|q| !q.is_open()));
+
+                match last {
+                    Some(question) => {
+                        question.answer = None;
+                        question.skipped = false;
+                        let (index, text) = (question.index, question.question.clone());
+
+                        if self.stage == Stage::Resume {
+                            self.transition(Trigger::Back, Some(format!("question {index}")))?;
+                        }
+                        self.add_note(&format!("The user went back: question {index} \"{text}\" is open again, ask it again."));
+                        Ok(Back::Reopened(index, text))
+                    }
+                    None => {
+                        self.transition(Trigger::Back, None)?;
+                        self.questions = None;
+                        self.add_note("The user went back: the questions were discarded, make a new list.");
+                        Ok(Back::QuestionsDiscarded)
+                    }
+                }
+            }
+            Stage::Questions => {
+                self.transition(Trigger::Back, None)?;
+                self.profession = None;
+                self.add_note("The user went back: ask for the profession again.");
+                Ok(Back::ProfessionCleared)
+            }
+            Stage::Review => {
+                self.transition(Trigger::Back, None)?;
+                self.add_note("The user closed the review: the CV stays as it is.");
+                Ok(Back::ReviewClosed)
+            }
+            Stage::Profession => Err("there is nothing to go back to"),
+            Stage::Done => Err("the CV is ready, use regenerate or reset"),
+        }
+    }
+
+    /// Discards the generated CV so it's made again from the same answers; returns the old file name.
+    pub fn regenerate(&mut self) -> Result<Option<String>, &'static str> {
+        match self.stage {
+            Stage::Resume => return Ok(None),
+            Stage::Done | Stage::Review => {}
+            _ => return Err("there is no CV to regenerate yet"),
+        }
+
+        self.transition(Trigger::Regenerate, None)?;
+        self.resume_prompt_version = None;
+        self.resume_html = None;
+        Ok(self.resume.take())
+    }
 
-        Need::Profession
+    /// Starts over with another profession, keeping the rest of the conversation;
+    /// returns the file name of a CV made for the old one.
+    pub fn change_profession(&mut self, profession: Option<&str>) -> Result<Option<String>, &'static str> {
+        self.transition(Trigger::ChangeProfession, profession.map(str::to_string))?;
+        self.profession = None;
+        self.questions = None;
+        self.resume_prompt_version = None;
+        self.resume_html = None;
+        let resume = self.resume.take();
+
+        match profession {
+            Some(profession) => {
+                self.set_profession(profession)?;
+                self.add_note(&format!("The user changed the profession to \"{profession}\"."));
+            }
+            None => self.add_note("The user wants to change the profession, ask for the new one."),
+        }
+        Ok(resume)
     }
 
-    pub async fn save(&self, pool: &Pool<Postgres>) -> Result<(), &'static str> {
-        db::save_user(pool, UserWithCustomMessages::from_original(self)).await.map_err(|e| e)?;
+    pub async fn save(&mut self, pool: &Pool<Postgres>) -> Result<(), &'static str> {
+        db::save_user(pool, UserWithCustomMessages::from_original(self)).await?;
+
+        let transitions = std::mem::take(&mut self.transitions);
+        if !transitions.is_empty() {
+            db::add_dialogue_events(pool, self.id as i32, &transitions).await
+                .map_err(|_| "Failed to save dialogue events")?;
+        }
+
+        let snapshots = std::mem::take(&mut self.snapshots);
+        if !snapshots.is_empty() {
+            db::add_dialogue_snapshots(pool, self.id as i32, &snapshots).await
+                .map_err(|_| "Failed to save dialogue snapshots")?;
+        }
         Ok(())
     }
 
-    pub fn set_profession(&mut self, profession: &str) {
+    /// Interview language, `None` until it is set or detected.
+    pub fn get_language(&self) -> Option<String> {
+        self.language.clone()
+    }
+
+    /// Language of the generated CV; the interview language unless set separately.
+    pub fn get_cv_language(&self) -> Option<String> {
+        self.cv_language.clone().or_else(|| self.language.clone())
+    }
+
+    pub fn set_language(&mut self, language: &str) {
+        self.language = Some(language.to_string());
+    }
+
+    pub fn set_cv_language(&mut self, language: &str) {
+        self.cv_language = Some(language.to_string());
+    }
+
+    /// Sets the interview language from a client hint or the user's text, unless it is already known.
+    pub fn detect_language(&mut self, language_code: Option<&str>, text: &str) {
+        if self.language.is_some() {
+            return;
+        }
+
+        let detected = language_code.and_then(language::normalize)
+            .or_else(|| language::detect(text));
+        if let Some(detected) = detected {
+            self.set_language(detected);
+        }
+    }
+
+    pub fn set_profession(&mut self, profession: &str) -> Result<(), &'static str> {
+        self.transition(Trigger::ProfessionSaved, Some(profession.to_string()))?;
         self.profession = Some(profession.to_string());
+        Ok(())
     }
 
-    pub fn set_questions(&mut self, questions: Vec<String>) {
-        self.questions =
-            Some(
-                questions
-                    .iter()
-                    .enumerate()
-                    .map(|(ind, q)| Question::new(ind as u8, q))
-                    .collect()
-            );
+    /// Starts the survey from an imported profile instead of the interview; returns how many questions it answered.
+    pub fn import_profile(&mut self, profile: Profile, source: &str) -> Result<usize, &'static str> {
+        match (self.stage, &profile.profession) {
+            (Stage::Profession, Some(profession)) => self.set_profession(&profession.clone())?,
+            (Stage::Profession, None) => return Err("no job title found to start from"),
+            (Stage::Questions, _) => {}
+            _ => return Err("a profile can only be imported before the questions are ready"),
+        }
+
+        let questions = profile.questions();
+        let answered = questions.iter().filter(|q| q.answer.is_some()).count();
+        self.set_questions(questions)?;
+        self.add_note(&format!(
+            "The user imported their {source}; the survey is filled in from it. Ask only about the questions without an answer.",
+        ));
+        Ok(answered)
+    }
+
+    /// Saves the generated survey grouped by section, keeping the model's order within a section.
+    pub fn set_questions(&mut self, mut questions: Vec<QuestionInput>) -> Result<(), &'static str> {
+        self.transition(Trigger::QuestionsSaved, Some(format!("{} questions", questions.len())))?;
+        questions.sort_by_key(|q| q.section);
+        self.questions = Some(new_questions(questions));
+
+        if self.all_answered() {
+            self.transition(Trigger::AllAnswered, None)?;
+        }
+        Ok(())
     }
 
     pub fn set_answer(&mut self, ind: u8, answer: &str) -> Option<&'static str> {
         if let Some(ref mut questions) = &mut self.questions {
             if let Some(q) = questions.get_mut(ind as usize) {
-                q.set_answer(answer);
+                match (is_not_applicable(answer), q.required) {
+                    (true, true) => return Some("the question is required"),
+                    (true, false) => q.skip(),
+                    (false, _) => q.set_answer(answer),
+                }
+                if self.stage == Stage::Answers && self.all_answered() {
+                    return self.transition(Trigger::AllAnswered, None).err();
+                }
                 return None;
             }
 
             return Some("invalid question index");
         };
 
-        return Some("no questions");
+        Some("no questions")
+    }
+
+    pub fn add_entry(&mut self, ind: u8, entry: Entry) -> Option<&'static str> {
+        let Some(questions) = self.questions.as_mut() else {
+            return Some("no questions");
+        };
+        match questions.get_mut(ind as usize) {
+            Some(q) => q.add_entry(entry).err(),
+            None => Some("invalid question index"),
+        }
+    }
+
+    /// Returns the file name of a CV made before the review that led to this one.
+    pub fn set_resume(&mut self, resume: &str, html: &str, prompt_version: &str) -> Result<Option<String>, &'static str> {
+        self.transition(Trigger::ResumeSaved, Some(resume.to_string()))?;
+        self.resume_html = Some(html.to_string());
+        self.resume_prompt_version = Some(prompt_version.to_string());
+        Ok(self.resume.replace(resume.to_string()))
     }
 
-    pub fn set_resume(&mut self, resume: &str) {
-        self.resume = Some(resume.to_string());
+    /// Opens the review of a finished CV.
+    pub fn start_review(&mut self) -> Result<(), &'static str> {
+        self.transition(Trigger::ReviewStarted, None)
+    }
+
+    /// Closes the review to make the CV again from the improved answers. The old CV is kept
+    /// until the new one replaces it, so it can still be downloaded meanwhile.
+    pub fn finish_review(&mut self, changes: &str) -> Result<(), &'static str> {
+        self.transition(Trigger::Regenerate, Some(changes.to_string()))
     }
 
     pub fn get_resume(&self) -> Option<String> {
         self.resume.clone()
     }
 
-    pub fn reset(&mut self) {
+    pub fn get_resume_html(&self) -> Option<&str> {
+        self.resume_html.as_deref()
+    }
+
+    /// Keeps only the latest cover letter; returns the file name of the one it replaces.
+    pub fn set_cover_letter(&mut self, cover_letter: &str, prompt_version: &str) -> Option<String> {
+        self.cover_letter_prompt_version = Some(prompt_version.to_string());
+        self.cover_letter.replace(cover_letter.to_string())
+    }
+
+    pub fn get_cover_letter(&self) -> Option<String> {
+        self.cover_letter.clone()
+    }
+
+    /// Returns the file name of the photo it replaces.
+    pub fn set_photo(&mut self, photo: Option<&str>) -> Option<String> {
+        std::mem::replace(&mut self.photo, photo.map(str::to_string))
+    }
+
+    pub fn get_photo(&self) -> Option<String> {
+        self.photo.clone()
+    }
+
+    pub fn reset(&mut self, source: &str) {
+        self.transition(Trigger::Reset, Some(source.to_string())).expect("reset is allowed from any stage");
+
         let mut new_user = User::new(self.id);
+        new_user.transitions = std::mem::take(&mut self.transitions);
+        new_user.snapshots = std::mem::take(&mut self.snapshots);
+        new_user.tenant = self.tenant.clone();
+        new_user.language = self.language.clone();
+        new_user.cv_language = self.cv_language.clone();
         new_user.tokens_spent = self.tokens_spent;
+        new_user.prompt_tokens_spent = self.prompt_tokens_spent;
+        new_user.completion_tokens_spent = self.completion_tokens_spent;
         *self = new_user;
     }
 
-    pub fn get_messages(&self, limit: Option<usize>) -> Vec<ChatCompletionRequestMessage> {
-        if !limit.is_some() {
-            return self.messages.clone();
-        }
+    /// Messages not yet folded into the summary.
+    fn unsummarized_messages(&self) -> &[ChatCompletionRequestMessage] {
+        let start = (self.summarized_messages as usize).min(self.messages.len());
+        &self.messages[start..]
+    }
+
+    fn summary_message(&self) -> Option<ChatCompletionRequestMessage> {
+        self.summary.as_ref().map(|summary| ChatCompletionRequestMessage::System(
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(format!("Summary of the earlier conversation:\n{summary}"))
+                .build().unwrap()
+        ))
+    }
 
-        let limit = limit.unwrap();
-        let mut messages = vec![];
+    /// The latest turns that fit in `limit` tokens, after the summary. The latest turn is always kept,
+    /// with its text cut to fit if it's too long on its own, so the user's last message isn't lost.
+    pub fn get_messages(&self, limit: Option<usize>, model: &str) -> Vec<ChatCompletionRequestMessage> {
+        let history = self.unsummarized_messages();
+        let summary = self.summary_message();
 
-        let mut counter = 0;
-        for m in self.messages.iter().rev() {
-            let content = match m.clone() {
-                ChatCompletionRequestMessage::System(sm) => sm.content,
-                ChatCompletionRequestMessage::User(um) => match um.content {
-                    ChatCompletionRequestUserMessageContent::Text(t) => t,
-                    ChatCompletionRequestUserMessageContent::Array(_) => "".to_string(),
-                },
-                ChatCompletionRequestMessage::Assistant(am) => am.content.unwrap_or("".to_string()),
-                ChatCompletionRequestMessage::Tool(tm) => tm.content,
-                ChatCompletionRequestMessage::Function(fm) => fm.content.unwrap_or("".to_string()),
-            };
-            counter = counter + content.len();
+        let Some(limit) = limit else {
+            return summary.into_iter().chain(history.iter().cloned()).collect();
+        };
+
+        let mut counter = summary.as_ref().map_or(0, |m| tokens::count_message(model, m));
+        let groups = message_groups(history);
+        let mut start = history.len();
+        for group in groups.iter().rev() {
+            counter += count_group(model, &history[group.clone()]);
 
             if counter > limit {
                 break;
             }
-            messages.push(m.clone());
+            start = group.start;
         }
-        messages.reverse();
 
-        return messages;
+        let latest = match groups.last() {
+            // not even the latest turn fits
+            Some(group) if start > group.start => {
+                let budget = limit.saturating_sub(summary.as_ref().map_or(0, |m| tokens::count_message(model, m)));
+                let budget = budget / group.len().max(1);
+                history[group.clone()].iter().map(|m| tokens::truncate_message(model, m, budget)).collect()
+            }
+            _ => history[start..].to_vec(),
+        };
+
+        summary.into_iter().chain(latest).collect()
+    }
+
+    /// Oldest turns to fold into the summary once the unsummarized history overflows `limit`.
+    /// Enough turns are taken to bring the rest down to half the limit; the latest turn is always kept.
+    pub fn messages_to_compact(&self, limit: usize, model: &str) -> Option<Vec<ChatCompletionRequestMessage>> {
+        let history = self.unsummarized_messages();
+        let groups = message_groups(history);
+
+        let mut remaining: usize = groups.iter().map(|g| count_group(model, &history[g.clone()])).sum();
+        if remaining <= limit {
+            return None;
+        }
+
+        let mut end = 0;
+        for group in &groups[..groups.len().saturating_sub(1)] {
+            if remaining <= limit / 2 {
+                break;
+            }
+            remaining -= count_group(model, &history[group.clone()]);
+            end = group.end;
+        }
+
+        match end {
+            0 => None,
+            end => Some(history[..end].to_vec()),
+        }
+    }
+
+    pub fn get_summary(&self) -> Option<String> {
+        self.summary.clone()
+    }
+
+    pub fn set_summary(&mut self, summary: &str, compacted_messages: usize) {
+        self.summary = Some(summary.to_string());
+        self.summarized_messages += compacted_messages as u32;
     }
 
     pub fn add_message(&mut self, message: ChatCompletionRequestMessage) {
@@ -218,11 +985,20 @@ impl User {
     }
 
     pub fn add_func_success(&mut self, call_id: &str, _: &str) {
+        self.add_func_result(call_id, "success");
+    }
+
+    /// Tells the model why the call had no effect, so it can ask the user again.
+    pub fn add_func_error(&mut self, call_id: &str, error: &str) {
+        self.add_func_result(call_id, &format!("error: {error}"));
+    }
+
+    fn add_func_result(&mut self, call_id: &str, content: &str) {
         self.add_message(
             ChatCompletionRequestMessage::Tool(
                 ChatCompletionRequestToolMessageArgs::default()
                     .tool_call_id(call_id)
-                    .content("success")
+                    .content(content)
                     .build().unwrap()
             )
         );
@@ -237,11 +1013,149 @@ impl User {
         None
     }
 
-    pub fn add_tokens_spent(&mut self, tokens: u32) {
-        self.tokens_spent += tokens;
+    pub fn add_tokens_spent(&mut self, usage: &TokenUsage) {
+        self.tokens_spent += usage.total();
+        self.prompt_tokens_spent += usage.prompt_tokens;
+        self.completion_tokens_spent += usage.completion_tokens;
+    }
+
+    pub fn get_tokens_spent(&self) -> u32 {
+        self.tokens_spent
     }
 
     pub fn not_enough_tokens(&self, tokens: u32) -> bool {
         self.tokens_spent >= tokens
     }
-}
\ No newline at end of file
+
+    pub fn remaining_tokens(&self, tokens: u32) -> u32 {
+        tokens.saturating_sub(self.tokens_spent)
+    }
+}
+
+fn new_questions(questions: Vec<QuestionInput>) -> Vec<Question> {
+    questions.into_iter()
+        .enumerate()
+        .map(|(ind, q)| Question::new(ind as u8, q))
+        .collect()
+}
+
+fn messages_to_value(messages: &[ChatCompletionRequestMessage]) -> Value {
+    serde_json::to_value(messages.iter().map(|msg| Message::from_original(msg.clone())).collect::<Vec<_>>()).unwrap_or_default()
+}
+
+fn messages_from_value(messages: Value) -> Vec<ChatCompletionRequestMessage> {
+    serde_json::from_value::<Vec<Message>>(messages).unwrap_or_default()
+        .into_iter().map(|msg| msg.into_original()).collect()
+}
+
+/// Splits history into turns so that tool results always stay with the assistant message that requested them.
+fn message_groups(messages: &[ChatCompletionRequestMessage]) -> Vec<Range<usize>> {
+    let mut groups: Vec<Range<usize>> = vec![];
+
+    for (ind, m) in messages.iter().enumerate() {
+        match (m, groups.last_mut()) {
+            (ChatCompletionRequestMessage::Tool(_), Some(group)) => group.end = ind + 1,
+            // a tool result without its request is invalid on its own
+            (ChatCompletionRequestMessage::Tool(_), None) => {}
+            _ => groups.push(ind..ind + 1),
+        }
+    }
+
+    groups
+}
+
+fn count_group(model: &str, messages: &[ChatCompletionRequestMessage]) -> usize {
+    messages.iter().map(|m| tokens::count_message(model, m)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use async_openai::types::{
+        ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionToolType, FunctionCall,
+    };
+    use super::*;
+
+    const MODEL: &str = "gpt-4o";
+
+    fn user_message(text: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestUserMessageArgs::default().content(text).build().unwrap().into()
+    }
+
+    fn tool_call(call_id: &str) -> ChatCompletionRequestMessage {
+        ChatCompletionRequestAssistantMessageArgs::default()
+            .tool_calls(vec![ChatCompletionMessageToolCall {
+                id: call_id.to_string(),
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall { name: "save_answer".to_string(), arguments: "{}".to_string() },
+            }])
+            .build().unwrap()
+            .into()
+    }
+
+    fn user_with(messages: Vec<ChatCompletionRequestMessage>) -> User {
+        let mut user = User::new(1);
+        messages.into_iter().for_each(|m| user.add_message(m));
+        user
+    }
+
+    fn texts(messages: &[ChatCompletionRequestMessage]) -> Vec<String> {
+        messages.iter().map(tokens::message_text).collect()
+    }
+
+    #[test]
+    fn without_a_limit_the_whole_history_is_kept() {
+        let user = user_with((0..5).map(|i| user_message(&format!("message {i}"))).collect());
+        assert_eq!(user.get_messages(None, MODEL).len(), 5);
+    }
+
+    #[test]
+    fn latest_turns_that_fit_are_kept() {
+        let user = user_with((0..10).map(|i| user_message(&format!("message {i}"))).collect());
+        let turn = tokens::count_message(MODEL, &user_message("message 9"));
+
+        let messages = user.get_messages(Some(turn * 3), MODEL);
+        assert_eq!(texts(&messages), ["message 7", "message 8", "message 9"]);
+    }
+
+    #[test]
+    fn tool_results_are_kept_with_their_call() {
+        let mut user = user_with(vec![user_message("first"), tool_call("call_1")]);
+        user.add_func_success("call_1", "save_answer");
+        user.add_message(user_message("last"));
+
+        let call_group = count_group(MODEL, &user.messages[1..3]);
+        let last = tokens::count_message(MODEL, &user.messages[3]);
+        // One token short of the call and its result: neither may be kept without the other.
+        let messages = user.get_messages(Some(call_group + last - 1), MODEL);
+        assert_eq!(texts(&messages), ["last"]);
+
+        let messages = user.get_messages(Some(call_group + last), MODEL);
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[0], ChatCompletionRequestMessage::Assistant(_)));
+    }
+
+    #[test]
+    fn summary_comes_first_and_counts_against_the_limit() {
+        let mut user = user_with((0..4).map(|i| user_message(&format!("message {i}"))).collect());
+        user.set_summary("The user is a backend engineer.", 2);
+        let summary = tokens::count_message(MODEL, &user.summary_message().unwrap());
+        let turn = tokens::count_message(MODEL, &user_message("message 3"));
+
+        let messages = user.get_messages(Some(summary + turn), MODEL);
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0], ChatCompletionRequestMessage::System(_)));
+        assert_eq!(tokens::message_text(&messages[1]), "message 3");
+    }
+
+    #[test]
+    fn latest_turn_is_cut_to_fit_rather_than_dropped() {
+        let long = "word ".repeat(500);
+        let user = user_with(vec![user_message("earlier"), user_message(&long)]);
+
+        let messages = user.get_messages(Some(100), MODEL);
+        assert_eq!(messages.len(), 1);
+        assert!(long.starts_with(&tokens::message_text(&messages[0])));
+        assert!(tokens::count_message(MODEL, &messages[0]) <= 100);
+    }
+}
@@ -0,0 +1,146 @@
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::error;
+use crate::db;
+use crate::dialogue::Dialogue;
+use crate::import::Upload;
+use crate::reply::{bad_gateway, conflict, unprocessable};
+use crate::stage::Stage;
+use crate::storage::{delete_unused, load};
+use crate::user::User;
+use crate::{get_bucket_name, new_asker, store_resume, AppState, OpenAI};
+
+const MAX_DESCRIPTION_CHARS: usize = 20_000;
+const MAX_TITLE_CHARS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct NewVacancy {
+    title: Option<String>,
+    description: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailorRequest {
+    open_ai: Option<OpenAI>,
+    max_tokens: Option<u32>,
+}
+
+/// A vacancy without a title is called by the first line of its description.
+async fn add(app_state: &AppState, id: i32, title: Option<String>, description: &str) -> Response {
+    let description = description.trim();
+    if description.is_empty() {
+        return unprocessable("the job description is empty");
+    }
+    if description.chars().count() > MAX_DESCRIPTION_CHARS {
+        return unprocessable(&format!("the job description is longer than {MAX_DESCRIPTION_CHARS} characters"));
+    }
+    let title = title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| description.lines().next().unwrap_or_default().trim().to_string());
+    let title: String = title.chars().take(MAX_TITLE_CHARS).collect();
+
+    match db::add_vacancy(&app_state.pool, id, &title, description).await {
+        Ok(vacancy) => (StatusCode::CREATED, Json(vacancy)).into_response(),
+        Err(e) => {
+            error!("Failed to add vacancy: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn vacancies_get(Path(id): Path<i32>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(_)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match db::load_vacancies(&app_state.pool, id).await {
+        Ok(vacancies) => Json(vacancies).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Attaches a pasted job description.
+pub async fn vacancy_create(Path(id): Path<i32>, State(app_state): State<AppState>, Json(vacancy): Json<NewVacancy>) -> Response {
+    let Ok(Some(_)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    add(&app_state, id, vacancy.title, &vacancy.description).await
+}
+
+/// Attaches a job description from a PDF, DOCX or text file, with an optional `title` field.
+pub async fn vacancy_upload(Path(id): Path<i32>, State(app_state): State<AppState>, multipart: Multipart) -> Response {
+    let Ok(Some(_)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut upload = match Upload::read(multipart).await {
+        Ok(upload) => upload,
+        Err(response) => return response,
+    };
+    let title = upload.fields.remove("title");
+    match upload.text().await {
+        Ok(description) => add(&app_state, id, title, &description).await,
+        Err(response) => response,
+    }
+}
+
+pub async fn vacancy_delete(Path((id, vacancy_id)): Path<(i32, i32)>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(vacancy)) = db::load_vacancy(&app_state.pool, id, vacancy_id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if db::delete_vacancy(&app_state.pool, id, vacancy_id).await.is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    delete_unused(&app_state.s3_client, &get_bucket_name(), vacancy.resume).await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Generates the CV for the vacancy from the current answers, replacing the one made before.
+pub async fn vacancy_cv_post(Path((id, vacancy_id)): Path<(i32, i32)>, State(app_state): State<AppState>, Json(request): Json<TailorRequest>) -> Response {
+    let Ok(Some(user)) = User::get_user(&app_state.pool, id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Ok(Some(vacancy)) = db::load_vacancy(&app_state.pool, id, vacancy_id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !matches!(user.get_stage(), Stage::Resume | Stage::Done | Stage::Review) {
+        return conflict("the survey isn't finished yet");
+    }
+
+    let user_photo = user.get_photo();
+    let asker = new_asker(&app_state, user.get_tenant(), request.open_ai);
+    let mut dialogue = Dialogue::new(user, asker, None, request.max_tokens);
+    let tailored = dialogue.tailor_resume(&vacancy.description).await;
+    dialogue.save_user(&app_state.pool).await;
+    let (html, prompt_version) = match tailored {
+        Ok(tailored) => tailored,
+        Err(e) => return bad_gateway(&e),
+    };
+
+    let resume_name = store_resume(&app_state, &html, user_photo.as_deref()).await;
+    if let Err(e) = db::set_vacancy_resume(&app_state.pool, vacancy_id, &resume_name, &html, &prompt_version).await {
+        error!("Failed to save the CV of vacancy {vacancy_id}: {e:?}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    delete_unused(&app_state.s3_client, &get_bucket_name(), vacancy.resume).await;
+    Json(json!({ "generated": true })).into_response()
+}
+
+pub async fn vacancy_cv_get(Path((id, vacancy_id)): Path<(i32, i32)>, State(app_state): State<AppState>) -> Response {
+    let Ok(Some(vacancy)) = db::load_vacancy(&app_state.pool, id, vacancy_id).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(name) = vacancy.resume else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let bytes = load(&app_state.s3_client, &get_bucket_name(), &name).await.expect("Failed s3_load");
+    let headers = [
+        (header::CONTENT_TYPE, "application/pdf; charset=utf-8"),
+        (header::CONTENT_DISPOSITION, "attachment; filename=\"cv.pdf\""),
+    ];
+    (headers, bytes).into_response()
+}
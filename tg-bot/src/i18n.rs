@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+/// Message catalogues, one JSON object of `key: text` per language.
+const CATALOGUES: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.json")),
+    ("ru", include_str!("locales/ru.json")),
+    ("uk", include_str!("locales/uk.json")),
+    ("de", include_str!("locales/de.json")),
+    ("es", include_str!("locales/es.json")),
+];
+
+type Catalogue = HashMap<String, String>;
+
+fn catalogues() -> &'static HashMap<&'static str, Catalogue> {
+    static CATALOGUES_CELL: OnceLock<HashMap<&'static str, Catalogue>> = OnceLock::new();
+    CATALOGUES_CELL.get_or_init(|| {
+        CATALOGUES.iter()
+            .map(|(language, json)| {
+                let catalogue = serde_json::from_str(json)
+                    .unwrap_or_else(|e| panic!("Invalid message catalogue \"{language}\": {e}"));
+                (*language, catalogue)
+            })
+            .collect()
+    })
+}
+
+/// Primary subtag of a Telegram `language_code` such as `pt-br`.
+fn primary(language: Option<&str>) -> &str {
+    language
+        .and_then(|l| l.split(['-', '_']).next())
+        .unwrap_or(DEFAULT_LANGUAGE)
+}
+
+/// Text for `key` in the given language, falling back to English and then to the key itself.
+pub fn t(language: Option<&str>, key: &str) -> String {
+    let catalogues = catalogues();
+    [primary(language), DEFAULT_LANGUAGE].into_iter()
+        .find_map(|l| catalogues.get(l).and_then(|c| c.get(key)))
+        .cloned()
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Like [`t`], substituting `{name}` placeholders.
+pub fn t_with(language: Option<&str>, key: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(t(language, key), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
@@ -1,23 +1,42 @@
+mod i18n;
+mod speech;
+
 use reqwest::{Client, StatusCode};
+use reqwest::multipart::{Form, Part};
 use serde::{Deserialize, Serialize};
-use serde_json::{Value};
+use serde_json::{json, Value};
 use sqlx::{Pool, Postgres, postgres::PgPoolOptions};
+use std::collections::HashSet;
 use std::env;
+use std::future::Future;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use teloxide::{prelude::*};
 use teloxide::utils::command::BotCommands;
 use chrono::{Utc, DateTime};
-use teloxide::types::InputFile;
+use teloxide::net::Download;
+use teloxide::types::{ChatAction, Document, FileMeta, InputFile, MessageId, Voice};
 use tempfile::NamedTempFile;
+use tokio::sync::oneshot;
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 use tracing::{error, info};
 use uuid::Uuid;
+use crate::i18n::{t, t_with};
+use crate::speech::SpeechToText;
+
+
+/// The api takes uploads up to this size.
+const MAX_FILE_BYTES: u32 = 10 * 1024 * 1024;
 
+/// How often the typing indicator, which Telegram shows for 5 seconds, is renewed and the api status polled.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(4);
 
 fn get_api_url() -> String {
     env::var("API_URL").expect("API_URL must be set")
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct User {
     id: i32,
@@ -32,6 +51,47 @@ struct User {
 #[derive(Debug, Serialize, Deserialize)]
 struct ApiMessage {
     text: String,
+    language_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCommandReply {
+    message: String,
+    generated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCommandError {
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiImportReply {
+    message: String,
+    generated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAtsReport {
+    score: u8,
+    keywords: Option<ApiAtsKeywords>,
+    suggestions: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAtsKeywords {
+    coverage: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiStatus {
+    step: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiLanguage {
+    language: Option<String>,
+    cv_language: Option<String>,
 }
 
 #[derive(Clone, BotCommands)]
@@ -44,6 +104,50 @@ enum Command {
     #[command(description = "generate an invite link.")]
     GenerateInvite,
     CV,
+    #[command(description = "set the interview language, e.g. /language en")]
+    Language(String),
+    #[command(description = "set the CV language, e.g. /cvlanguage de")]
+    CvLanguage(String),
+    #[command(description = "start over, deleting your answers and CV.")]
+    Reset,
+    #[command(description = "show how many questions are answered.")]
+    Progress,
+    #[command(description = "skip the current question.")]
+    Skip,
+    #[command(description = "undo the last answer or step.")]
+    Back,
+    #[command(description = "return to the state before the last stage change, even after /reset.")]
+    Undo,
+    #[command(description = "generate the CV again from the same answers.")]
+    Regenerate,
+    #[command(description = "change the profession, e.g. /profession Data Engineer")]
+    Profession(String),
+    #[command(description = "get a critique of the CV and improve it together.")]
+    Review,
+    #[command(description = "set the photo of the CV: send this, then the photo.")]
+    Photo,
+    #[command(description = "check the CV for ATS issues; add a job description to compare keywords, e.g. /ats <job description>")]
+    Ats(String),
+}
+
+/// Language of the Telegram client, used until the user has a language in the api.
+fn telegram_language(msg: &Message) -> Option<String> {
+    msg.from().and_then(|u| u.language_code.clone())
+}
+
+/// Language for bot replies: the interview language picked with /language or detected by the api,
+/// else the Telegram client's.
+async fn user_language(params: &ConfigParameters, msg: &Message) -> Option<String> {
+    let Some(user_id) = get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") else {
+        return telegram_language(msg);
+    };
+    match get_user_language(&params.client, user_id).await {
+        Ok(languages) => languages.language.or_else(|| telegram_language(msg)),
+        Err(e) => {
+            error!("get_user_language error:\n{e:?}");
+            telegram_language(msg)
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -85,9 +189,93 @@ async fn get_user_resume(client: &Client, user_id: i32, file: &mut NamedTempFile
     Ok(true)
 }
 
-async fn send_message(client: &Client, user_id: i32, text: &str) -> Result<String, reqwest::Error> {
+async fn get_user_language(client: &Client, user_id: i32) -> Result<ApiLanguage, reqwest::Error> {
+    let api_url = get_api_url();
+    let response = client.get(format!("{api_url}/users/{}/language", user_id)).send().await?;
+    response.error_for_status()?.json().await
+}
+
+async fn set_user_language(client: &Client, user_id: i32, language: ApiLanguage) -> Result<bool, reqwest::Error> {
+    let api_url = get_api_url();
+    let response = client.put(format!("{api_url}/users/{}/language", user_id))
+        .json(&language)
+        .send().await?;
+    Ok(response.status().is_success())
+}
+
+/// Runs a dialogue command; a command that isn't possible right now comes back as `Ok` with the reason.
+async fn send_command(client: &Client, user_id: i32, command: Value) -> Result<ApiCommandReply, reqwest::Error> {
+    let api_url = get_api_url();
+    let response = client.post(format!("{api_url}/users/{}/commands", user_id))
+        .json(&command)
+        .send().await?;
+
+    if response.status() == StatusCode::CONFLICT {
+        let error: ApiCommandError = response.json().await?;
+        return Ok(ApiCommandReply { message: error.error, generated: false });
+    }
+    response.error_for_status()?.json().await
+}
+
+/// Checks the CV for ATS issues; a CV that isn't generated yet comes back as `Err` with the reason.
+async fn check_ats(client: &Client, user_id: i32, description: Option<String>) -> Result<Result<ApiAtsReport, String>, reqwest::Error> {
     let api_url = get_api_url();
-    let message = ApiMessage { text: text.to_string() };
+    let response = client.post(format!("{api_url}/users/{}/ats", user_id))
+        .json(&json!({ "description": description }))
+        .send().await?;
+
+    if response.status() == StatusCode::CONFLICT {
+        let error: ApiCommandError = response.json().await?;
+        return Ok(Err(error.error));
+    }
+    Ok(Ok(response.error_for_status()?.json().await?))
+}
+
+/// Why the api didn't take an upload: `{"error": ...}` for the wrong stage, `{"errors": [...]}` for a bad file.
+async fn upload_error(response: reqwest::Response) -> Result<String, reqwest::Error> {
+    let body: Value = response.json().await?;
+    let reason = match (&body["error"], &body["errors"]) {
+        (Value::String(error), _) => error.clone(),
+        (_, Value::Array(errors)) => errors.iter().filter_map(Value::as_str).collect::<Vec<_>>().join("\n"),
+        _ => String::new(),
+    };
+    Ok(reason)
+}
+
+/// Sends a CV or a profile to fill in the answers; a file the api can't take comes back as `Err` with the reason.
+async fn import_file(client: &Client, user_id: i32, file_name: String, mime_type: Option<String>, bytes: Vec<u8>) -> Result<Result<ApiImportReply, String>, reqwest::Error> {
+    let api_url = get_api_url();
+    let mut part = Part::bytes(bytes).file_name(file_name);
+    if let Some(mime_type) = mime_type {
+        part = part.mime_str(&mime_type)?;
+    }
+    let response = client.post(format!("{api_url}/users/{}/import", user_id))
+        .multipart(Form::new().part("file", part))
+        .send().await?;
+
+    if matches!(response.status(), StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY | StatusCode::BAD_GATEWAY) {
+        return Ok(Err(upload_error(response).await?));
+    }
+    Ok(Ok(response.error_for_status()?.json().await?))
+}
+
+/// Sets the profile photo; an image the api can't take comes back as `Err` with the reason.
+async fn upload_photo(client: &Client, user_id: i32, bytes: Vec<u8>) -> Result<Result<(), String>, reqwest::Error> {
+    let api_url = get_api_url();
+    let response = client.put(format!("{api_url}/users/{}/photo", user_id))
+        .multipart(Form::new().part("file", Part::bytes(bytes).file_name("photo.jpg")))
+        .send().await?;
+
+    if response.status() == StatusCode::UNPROCESSABLE_ENTITY {
+        return Ok(Err(upload_error(response).await?));
+    }
+    response.error_for_status()?;
+    Ok(Ok(()))
+}
+
+async fn send_message(client: &Client, user_id: i32, text: &str, language_code: Option<String>) -> Result<String, reqwest::Error> {
+    let api_url = get_api_url();
+    let message = ApiMessage { text: text.to_string(), language_code };
     let response = client.post(format!("{api_url}/users/{}/message", user_id))
         .json(&message)
         .send().await?;
@@ -95,6 +283,63 @@ async fn send_message(client: &Client, user_id: i32, text: &str) -> Result<Strin
     Ok(reply)
 }
 
+async fn get_status(client: &Client, user_id: i32) -> Result<ApiStatus, reqwest::Error> {
+    let api_url = get_api_url();
+    let response = client.get(format!("{api_url}/users/{}/status", user_id)).send().await?;
+    response.error_for_status()?.json().await
+}
+
+/// Shows that a request is being worked on until `work` finishes: the typing indicator all along and,
+/// once the CV is being generated, a message edited as it goes through the steps and deleted at the end.
+async fn with_progress<T>(params: &ConfigParameters, bot: &Bot, msg: &Message, user_id: i32, work: impl Future<Output = T>) -> T {
+    let (done, finished) = oneshot::channel();
+    let progress = tokio::spawn(show_progress(
+        bot.clone(), params.client.clone(), msg.chat.id, user_id, user_language(params, msg).await, finished,
+    ));
+
+    let result = work.await;
+    let _ = done.send(());
+    let _ = progress.await;
+    result
+}
+
+async fn show_progress(bot: Bot, client: Client, chat_id: ChatId, user_id: i32, language: Option<String>, mut finished: oneshot::Receiver<()>) {
+    let mut message: Option<(MessageId, String)> = None;
+
+    loop {
+        if let Err(e) = bot.send_chat_action(chat_id, ChatAction::Typing).await {
+            error!("send_chat_action error:\n{e:?}");
+        }
+
+        let step = get_status(&client, user_id).await.map(|status| status.step).unwrap_or_else(|e| {
+            error!("get_status error:\n{e:?}");
+            String::new()
+        });
+        if matches!(step.as_str(), "writing_cv" | "rendering_pdf") && message.as_ref().is_none_or(|(_, shown)| *shown != step) {
+            let text = t(language.as_deref(), &format!("progress_{step}"));
+            let sent = match &message {
+                Some((id, _)) => bot.edit_message_text(chat_id, *id, text).await,
+                None => bot.send_message(chat_id, text).await,
+            };
+            match sent {
+                Ok(sent) => message = Some((sent.id, step)),
+                Err(e) => error!("progress message error:\n{e:?}"),
+            }
+        }
+
+        tokio::select! {
+            _ = &mut finished => break,
+            _ = tokio::time::sleep(PROGRESS_INTERVAL) => {}
+        }
+    }
+
+    if let Some((id, _)) = message {
+        if let Err(e) = bot.delete_message(chat_id, id).await {
+            error!("delete_message error:\n{e:?}");
+        }
+    }
+}
+
 async fn get_user_id(pool: &Pool<Postgres>, chat_id: i64) -> Result<Option<i32>, &'static str> {
     let api_user_id: Option<i32> = sqlx::query_scalar!("SELECT api_user_id FROM users WHERE chat_id = $1", chat_id)
         .fetch_optional(pool).await.unwrap();
@@ -114,27 +359,148 @@ async fn handle_message(
         None => {
             bot.send_message(
                 chat_id,
-                "You are not registered. Please contact with an admin to register.",
+                t(telegram_language(&msg).as_deref(), "not_registered"),
             ).await.unwrap();
             return Ok(());
         }
     };
 
-    let text = msg.text().unwrap();
-    let reply = send_message(&params.client, user_id, text).await.unwrap_or_else(
+    if let Some(text) = msg.text() {
+        handle_text(&params, &bot, &msg, user_id, text).await;
+    } else if let Some(voice) = msg.voice() {
+        handle_voice(&params, &bot, &msg, user_id, voice).await;
+    } else if let Some(photo) = msg.photo().and_then(|sizes| sizes.iter().max_by_key(|s| s.width)) {
+        handle_photo(&params, &bot, &msg, user_id, &photo.file).await;
+    } else if let Some(document) = msg.document() {
+        match &document.mime_type {
+            Some(mime) if mime.type_() == "image" => handle_photo(&params, &bot, &msg, user_id, &document.file).await,
+            _ => handle_document(&params, &bot, &msg, user_id, document).await,
+        }
+    } else {
+        let language = user_language(&params, &msg).await;
+        bot.send_message(chat_id, t(language.as_deref(), "unsupported_message")).await.unwrap();
+    }
+
+    Ok(())
+}
+
+async fn handle_text(params: &ConfigParameters, bot: &Bot, msg: &Message, user_id: i32, text: &str) {
+    let reply = with_progress(params, bot, msg, user_id, send_message(&params.client, user_id, text, telegram_language(msg))).await;
+    // The message may have set the language, so it's looked up after the api answered.
+    let language = user_language(params, msg).await;
+    let reply = reply.unwrap_or_else(
         |e| {
             error!("*Failed get api response:\n{:?}", e);
-            "Exception #5239740191".to_string()
+            t(language.as_deref(), "api_error")
         }
     );
 
     if &reply == "generated" {
-        handle_cv(&bot, &params.client, user_id, msg.chat.id).await.expect("foo");
+        handle_cv(bot, &params.client, user_id, msg.chat.id, language.as_deref()).await.expect("foo");
     }
 
-    bot.send_message(chat_id, reply).await.unwrap();
+    bot.send_message(msg.chat.id, reply).await.unwrap();
+}
 
-    Ok(())
+/// Downloads a file sent to the bot; replies to the user itself when that's not possible.
+async fn download(bot: &Bot, msg: &Message, file: &FileMeta, language: Option<&str>) -> Option<Vec<u8>> {
+    if file.size > MAX_FILE_BYTES {
+        bot.send_message(msg.chat.id, t(language, "file_too_large")).await.unwrap();
+        return None;
+    }
+
+    let mut bytes = vec![];
+    let downloaded = match bot.get_file(&file.id).await {
+        Ok(file) => bot.download_file(&file.path, &mut bytes).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match downloaded {
+        Ok(()) => Some(bytes),
+        Err(e) => {
+            error!("Failed to download file {}: {e}", file.id);
+            bot.send_message(msg.chat.id, t(language, "file_error")).await.unwrap();
+            None
+        }
+    }
+}
+
+/// Answers with the transcript of a voice message, echoing it first so the user can correct it.
+async fn handle_voice(params: &ConfigParameters, bot: &Bot, msg: &Message, user_id: i32, voice: &Voice) {
+    let language = user_language(params, msg).await;
+    let Some(speech) = &params.speech else {
+        bot.send_message(msg.chat.id, t(language.as_deref(), "voice_unsupported")).await.unwrap();
+        return;
+    };
+    let Some(bytes) = download(bot, msg, &voice.file, language.as_deref()).await else {
+        return;
+    };
+
+    let transcript = match speech.transcribe(&params.client, bytes).await {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            error!("transcribe error:\n{e:?}");
+            bot.send_message(msg.chat.id, t(language.as_deref(), "voice_error")).await.unwrap();
+            return;
+        }
+    };
+    if transcript.is_empty() {
+        bot.send_message(msg.chat.id, t(language.as_deref(), "voice_not_recognised")).await.unwrap();
+        return;
+    }
+
+    bot.send_message(msg.chat.id, t_with(language.as_deref(), "voice_transcript", &[("text", &transcript)])).await.unwrap();
+    handle_text(params, bot, msg, user_id, &transcript).await;
+}
+
+/// Saves the photo as the profile picture of the CV, if /photo asked for it: other photos sent
+/// in the chat, like a screenshot of a job ad, must not end up on the CV.
+async fn handle_photo(params: &ConfigParameters, bot: &Bot, msg: &Message, user_id: i32, file: &FileMeta) {
+    let language = user_language(params, msg).await;
+    if !params.awaiting_photo.lock().expect("photo lock poisoned").contains(&msg.chat.id) {
+        bot.send_message(msg.chat.id, t(language.as_deref(), "photo_command")).await.unwrap();
+        return;
+    }
+    let Some(bytes) = download(bot, msg, file, language.as_deref()).await else {
+        return;
+    };
+
+    let reply = match upload_photo(&params.client, user_id, bytes).await {
+        Ok(Ok(())) => {
+            params.awaiting_photo.lock().expect("photo lock poisoned").remove(&msg.chat.id);
+            t(language.as_deref(), "photo_saved")
+        }
+        Ok(Err(reason)) => reason,
+        Err(e) => {
+            error!("upload_photo error:\n{e:?}");
+            t(language.as_deref(), "api_error")
+        }
+    };
+    bot.send_message(msg.chat.id, reply).await.unwrap();
+}
+
+/// Imports an existing CV or profile to fill in the answers.
+async fn handle_document(params: &ConfigParameters, bot: &Bot, msg: &Message, user_id: i32, document: &Document) {
+    let language = user_language(params, msg).await;
+    let Some(bytes) = download(bot, msg, &document.file, language.as_deref()).await else {
+        return;
+    };
+
+    let file_name = document.file_name.clone().unwrap_or_else(|| "file".to_string());
+    let mime_type = document.mime_type.as_ref().map(|m| m.essence_str().to_string());
+    let reply = match with_progress(params, bot, msg, user_id, import_file(&params.client, user_id, file_name, mime_type, bytes)).await {
+        Ok(Ok(reply)) => {
+            if reply.generated {
+                handle_cv(bot, &params.client, user_id, msg.chat.id, language.as_deref()).await.expect("foo");
+            }
+            reply.message
+        }
+        Ok(Err(reason)) => reason,
+        Err(e) => {
+            error!("import_file error:\n{e:?}");
+            t(language.as_deref(), "api_error")
+        }
+    };
+    bot.send_message(msg.chat.id, reply).await.unwrap();
 }
 
 async fn handle_invite_link(params: ConfigParameters, bot: Bot, msg: &Message, invite_code: &str) -> Result<(), teloxide::RequestError> {
@@ -153,7 +519,7 @@ async fn handle_invite_link(params: ConfigParameters, bot: Bot, msg: &Message, i
     sqlx::query!("UPDATE users SET chat_id = $1, registered = $2 WHERE id = $3", chat_id.0, now, id)
         .execute(&params.pool).await.unwrap();  // TODO fix unwrap without result stop app
 
-    bot.send_message(chat_id, "You have successfully registered!").await.unwrap();
+    bot.send_message(chat_id, t(user_language(&params, msg).await.as_deref(), "registered")).await.unwrap();
 
     Ok(())
 }
@@ -163,13 +529,14 @@ async fn handle_command(
     bot: Bot, msg: Message,
     command: Command,
 ) -> Result<(), teloxide::RequestError> {
+    let language = user_language(&params, &msg).await;
     match command {
         Command::Help => {
             bot.send_message(msg.chat.id, Command::descriptions().to_string()).await.expect("foo");
         }
         Command::Start => {
             let code = &msg.text().unwrap()[7..];
-            if code.len() > 0 {
+            if !code.is_empty() {
                 handle_invite_link(params, bot, &msg, code).await.expect("foo");
             }
         }
@@ -188,14 +555,14 @@ async fn handle_command(
                 None => {
                     bot.send_message(
                         msg.chat.id,
-                        "You are not registered. Please contact with an admin to register.",
+                        t(language.as_deref(), "not_registered"),
                     ).await.unwrap();
                 }
             }
         }
         Command::GenerateInvite => {
             let invite_code = Uuid::new_v4().to_string();
-            let api_user_id = create_user(&params.client).await.or_else(|e| Err(format!("Failed create new user:\n{:?}", e))).unwrap();
+            let api_user_id = create_user(&params.client).await.map_err(|e| format!("Failed create new user:\n{:?}", e)).unwrap();
             let now = Utc::now();
 
             sqlx::query!(
@@ -209,27 +576,133 @@ async fn handle_command(
 
             let bot_name = env::var("BOT_NAME").expect("BOT_NAME must be set");
             let invite_link = format!("https://t.me/{bot_name}?start={}", invite_code);
-            bot.send_message(msg.chat.id, format!("Your invite link: {}", invite_link)).await.expect("foo");
+            bot.send_message(
+                msg.chat.id,
+                t_with(language.as_deref(), "invite_link", &[("link", &invite_link)]),
+            ).await.expect("foo");
         }
         Command::CV => {
             match get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") {
                 Some(user_id) => {
-                    handle_cv(&bot, &params.client, user_id, msg.chat.id).await.expect("foo");
+                    handle_cv(&bot, &params.client, user_id, msg.chat.id, language.as_deref()).await.expect("foo");
                 }
                 None => {
                     bot.send_message(
                         msg.chat.id,
-                        "You are not registered. Please contact with an admin to register.",
+                        t(language.as_deref(), "not_registered"),
                     ).await.unwrap();
                 }
             }
         }
+        Command::Language(code) => {
+            let body = ApiLanguage { language: Some(code.trim().to_string()), cv_language: None };
+            handle_language(&params, &bot, &msg, body, code.trim()).await;
+        }
+        Command::CvLanguage(code) => {
+            let body = ApiLanguage { language: None, cv_language: Some(code.trim().to_string()) };
+            handle_language(&params, &bot, &msg, body, code.trim()).await;
+        }
+        Command::Reset => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "reset" })).await,
+        Command::Progress => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "show_progress" })).await,
+        Command::Skip => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "skip_question" })).await,
+        Command::Back => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "back" })).await,
+        Command::Undo => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "undo" })).await,
+        Command::Regenerate => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "regenerate" })).await,
+        Command::Review => handle_dialogue_command(&params, &bot, &msg, json!({ "command": "review" })).await,
+        Command::Profession(profession) => {
+            let profession = Some(profession.trim().to_string()).filter(|p| !p.is_empty());
+            handle_dialogue_command(&params, &bot, &msg, json!({ "command": "change_profession", "profession": profession })).await
+        }
+        Command::Photo => {
+            let key = match get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") {
+                Some(_) => {
+                    params.awaiting_photo.lock().expect("photo lock poisoned").insert(msg.chat.id);
+                    "photo_send"
+                }
+                None => "not_registered",
+            };
+            bot.send_message(msg.chat.id, t(language.as_deref(), key)).await.unwrap();
+        }
+        Command::Ats(description) => {
+            let description = Some(description.trim().to_string()).filter(|d| !d.is_empty());
+            handle_ats(&params, &bot, &msg, description).await
+        }
     };
     Ok(())
 }
 
+async fn handle_dialogue_command(params: &ConfigParameters, bot: &Bot, msg: &Message, command: Value) {
+    let language = user_language(params, msg).await;
+
+    let Some(user_id) = get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") else {
+        bot.send_message(msg.chat.id, t(language.as_deref(), "not_registered")).await.unwrap();
+        return;
+    };
+
+    match with_progress(params, bot, msg, user_id, send_command(&params.client, user_id, command)).await {
+        Ok(reply) => {
+            if reply.generated {
+                handle_cv(bot, &params.client, user_id, msg.chat.id, language.as_deref()).await.expect("foo");
+            }
+            bot.send_message(msg.chat.id, reply.message).await.unwrap();
+        }
+        Err(e) => {
+            error!("send_command error:\n{e:?}");
+            bot.send_message(msg.chat.id, t(language.as_deref(), "api_error")).await.unwrap();
+        }
+    }
+}
 
-async fn handle_cv(bot: &Bot, client: &Client, user_id: i32, chat_id: ChatId) -> Result<(), &'static str> {
+async fn handle_ats(params: &ConfigParameters, bot: &Bot, msg: &Message, description: Option<String>) {
+    let language = user_language(params, msg).await;
+
+    let Some(user_id) = get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") else {
+        bot.send_message(msg.chat.id, t(language.as_deref(), "not_registered")).await.unwrap();
+        return;
+    };
+
+    let reply = match check_ats(&params.client, user_id, description).await {
+        Ok(Ok(report)) => {
+            let mut lines = vec![t_with(language.as_deref(), "ats_score", &[("score", &report.score.to_string())])];
+            if let Some(keywords) = report.keywords {
+                lines.push(t_with(language.as_deref(), "ats_keywords", &[("coverage", &keywords.coverage.to_string())]));
+            }
+            match report.suggestions.is_empty() {
+                true => lines.push(t(language.as_deref(), "ats_no_issues")),
+                false => lines.extend(report.suggestions.iter().map(|s| format!("• {s}"))),
+            }
+            lines.join("\n")
+        }
+        Ok(Err(reason)) => reason,
+        Err(e) => {
+            error!("check_ats error:\n{e:?}");
+            t(language.as_deref(), "api_error")
+        }
+    };
+
+    bot.send_message(msg.chat.id, reply).await.unwrap();
+}
+
+async fn handle_language(params: &ConfigParameters, bot: &Bot, msg: &Message, body: ApiLanguage, code: &str) {
+    let key = match get_user_id(&params.pool, msg.chat.id.0).await.expect("foo") {
+        Some(user_id) => match set_user_language(&params.client, user_id, body).await {
+            Ok(true) => "language_set",
+            Ok(false) => "language_unsupported",
+            Err(e) => {
+                error!("set_user_language error:\n{e:?}");
+                "api_error"
+            }
+        },
+        None => "not_registered",
+    };
+    // Looked up after the change, so a new interview language is confirmed in that language.
+    let language = user_language(params, msg).await;
+    let reply = t_with(language.as_deref(), key, &[("language", code)]);
+
+    bot.send_message(msg.chat.id, reply).await.unwrap();
+}
+
+async fn handle_cv(bot: &Bot, client: &Client, user_id: i32, chat_id: ChatId, language: Option<&str>) -> Result<(), &'static str> {
     let mut temp_file = NamedTempFile::new().unwrap();
     match get_user_resume(client, user_id, &mut temp_file).await {
         Ok(true) => {
@@ -241,14 +714,14 @@ async fn handle_cv(bot: &Bot, client: &Client, user_id: i32, chat_id: ChatId) ->
         Ok(false) => {
             bot.send_message(
                 chat_id,
-                "cv not found",
+                t(language, "cv_not_found"),
             ).await.unwrap();
         }
         Err(e) => {
             error!("get_user_cv error:\n{e:?}");
             bot.send_message(
                 chat_id,
-                "cv not found error",
+                t(language, "cv_error"),
             ).await.unwrap();
         }
     }
@@ -259,6 +732,10 @@ async fn handle_cv(bot: &Bot, client: &Client, user_id: i32, chat_id: ChatId) ->
 struct ConfigParameters {
     pool: Pool<Postgres>,
     client: Client,
+    speech: Option<SpeechToText>,
+    /// Chats that sent /photo and whose next photo goes on the CV; kept in memory, so after
+    /// a restart /photo has to be sent again.
+    awaiting_photo: Arc<Mutex<HashSet<ChatId>>>,
 }
 
 fn check_before() {
@@ -290,7 +767,12 @@ async fn main() {
 
     let client = Client::new();
 
-    let parameters = ConfigParameters { pool, client };
+    let speech = SpeechToText::from_env();
+    if speech.is_none() {
+        info!("SPEECH_TO_TEXT is not set, voice messages will be declined");
+    }
+
+    let parameters = ConfigParameters { pool, client, speech, awaiting_photo: Arc::default() };
 
     let handler = Update::filter_message()
         .branch(
@@ -0,0 +1,86 @@
+use std::env;
+use reqwest::Client;
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+
+const DEFAULT_API_BASE: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "whisper-1";
+
+/// Where voice messages are turned into text, chosen with `SPEECH_TO_TEXT`.
+#[derive(Debug, Clone)]
+pub enum SpeechToText {
+    /// The OpenAI transcription API, or any server compatible with it at `OPENAI_API_BASE`.
+    OpenAi {
+        api_base: String,
+        api_key: String,
+        model: String,
+    },
+    /// Returns `SPEECH_TO_TEXT_TRANSCRIPT` for every message without sending the audio anywhere,
+    /// so the bot can be run and tested without a speech service.
+    Local {
+        transcript: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Transcription {
+    text: String,
+}
+
+impl SpeechToText {
+    /// `SPEECH_TO_TEXT` is `openai` or `local`; unset, OpenAI is used when `OPENAI_API_KEY` is set.
+    /// `None` when voice messages can't be read.
+    pub fn from_env() -> Option<Self> {
+        let backend = env::var("SPEECH_TO_TEXT").ok()
+            .or_else(|| env::var("OPENAI_API_KEY").ok().map(|_| "openai".to_string()))?;
+
+        match backend.as_str() {
+            "openai" => Some(SpeechToText::OpenAi {
+                api_base: env::var("OPENAI_API_BASE").unwrap_or(DEFAULT_API_BASE.to_string()),
+                api_key: env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY must be set for SPEECH_TO_TEXT=openai"),
+                model: env::var("SPEECH_TO_TEXT_MODEL").unwrap_or(DEFAULT_MODEL.to_string()),
+            }),
+            "local" => Some(SpeechToText::Local {
+                transcript: env::var("SPEECH_TO_TEXT_TRANSCRIPT").unwrap_or("Voice message".to_string()),
+            }),
+            other => panic!("Unknown SPEECH_TO_TEXT backend \"{other}\", use openai or local"),
+        }
+    }
+
+    /// Text of an OGG/Opus voice note, as Telegram records them; the language is detected from the speech.
+    pub async fn transcribe(&self, client: &Client, audio: Vec<u8>) -> Result<String, reqwest::Error> {
+        match self {
+            SpeechToText::OpenAi { api_base, api_key, model } => {
+                let form = Form::new()
+                    .text("model", model.clone())
+                    .part("file", Part::bytes(audio).file_name("voice.ogg").mime_str("audio/ogg")?);
+                let transcription: Transcription = client.post(format!("{api_base}/audio/transcriptions"))
+                    .bearer_auth(api_key)
+                    .multipart(form)
+                    .send().await?
+                    .error_for_status()?
+                    .json().await?;
+                Ok(transcription.text.trim().to_string())
+            }
+            SpeechToText::Local { transcript } => Ok(transcript.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_backend_transcribes_a_voice_message() {
+        env::set_var("SPEECH_TO_TEXT", "local");
+        env::set_var("SPEECH_TO_TEXT_TRANSCRIPT", "I'm a data engineer");
+        let speech = SpeechToText::from_env().expect("local backend");
+        assert!(matches!(speech, SpeechToText::Local { .. }));
+
+        // The audio is never sent anywhere, so the client needs no server and the bytes can be anything.
+        let audio = b"OggS\x00\x02".to_vec();
+        let transcript = speech.transcribe(&Client::new(), audio).await.unwrap();
+        assert_eq!(transcript, "I'm a data engineer");
+    }
+}